@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Duration, Utc};
+use failure::{Error, ResultExt};
+use serde_json::{self, Value};
+use slog;
+use clock::Clock;
+use earnings::EarningsGuess;
+
+/// The version of the cache file format. Bump it whenever `Entry` or `EarningsGuess` change in a way that old files
+/// can't be read as, and convert the old version in `EarningsCache::load`.
+pub const VERSION : u64 = 2;
+
+/// Where the cache was kept before its location could be configured.
+pub static LEGACY_PATH : &'static str = ".earnings_cache.json";
+
+static FILE_NAME : &'static str = "earnings_cache.json";
+
+/// A guess whose last session was more than this many days ago is fetched again, however recently it was fetched.
+const STALE_DAYS : i64 = 2;
+
+/// A cached guess and when it was fetched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub fetched : DateTime<Utc>,
+    pub guess : EarningsGuess,
+}
+
+#[derive(Serialize)]
+struct CacheFile<'a> {
+    version : u64,
+    entries : &'a BTreeMap<String, Entry>,
+}
+
+/// The latest earnings guess for each symbol, so the sources don't have to be fetched on every run.
+#[derive(Debug, Clone, Default)]
+pub struct EarningsCache {
+    pub entries : BTreeMap<String, Entry>,
+}
+
+impl EarningsCache {
+    /// A missing file is an empty cache. Files in an older format are converted, and entries that can't be read are
+    /// dropped with a warning rather than failing the whole file.
+    pub fn load<P : AsRef<Path>>(logger : &slog::Logger, path : P) -> Result<EarningsCache, Error> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(EarningsCache::default())
+        }
+
+        let file = File::open(path).with_context(|_| format!("opening {}", path.display()))?;
+        let value : Value = serde_json::from_reader(file).with_context(|_| format!("parsing {}", path.display()))?;
+
+        // Version 1 was just the map of guesses.
+        let (version, entries) = match value.get("version") {
+            Some(version) => {
+                let version = version.as_u64().ok_or_else(|| format_err!("{}: bad version {}", path.display(), version))?;
+                (version, value.get("entries").cloned().unwrap_or(Value::Null))
+            },
+            None => (1, value.clone()),
+        };
+
+        if version > VERSION {
+            bail!("{} is format version {}, but only versions up to {} are supported", path.display(), version, VERSION);
+        }
+
+        let entries = match entries {
+            Value::Object(entries) => entries,
+            _ => bail!("{}: no cache entries", path.display()),
+        };
+
+        // Version 1 didn't record when guesses were fetched, but it can't have been after the file was last written.
+        let written = fs::metadata(path).and_then(|m| m.modified()).map(DateTime::<Utc>::from);
+
+        let mut cache = EarningsCache::default();
+        for (symbol, entry) in entries {
+            let parsed = match version {
+                1 => {
+                    let guess = serde_json::from_value::<EarningsGuess>(entry).map_err(Error::from);
+                    match written {
+                        Ok(ref written) => guess.map(|guess| Entry{ fetched: *written, guess: guess }),
+                        Err(ref e) => Err(format_err!("no modification time: {}", e)),
+                    }
+                },
+                _ => serde_json::from_value::<Entry>(entry).map_err(Error::from),
+            };
+
+            match parsed {
+                Ok(entry) => {
+                    cache.entries.insert(symbol, entry);
+                },
+                Err(e) => warn!(logger, "Dropping cached earnings date for {}: {}", symbol, e),
+            }
+        }
+
+        if version < VERSION {
+            info!(logger, "Converting earnings cache {} from format version {} to {}", path.display(), version, VERSION);
+        }
+
+        Ok(cache)
+    }
+
+    pub fn save<P : AsRef<Path>>(&self, path : P) -> Result<(), Error> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent).with_context(|_| format!("creating {}", parent.display()))?;
+            }
+        }
+
+        let contents = CacheFile{
+            version: VERSION,
+            entries: &self.entries,
+        };
+        let file = File::create(path).with_context(|_| format!("creating {}", path.display()))?;
+        serde_json::to_writer(file, &contents).with_context(|_| format!("writing {}", path.display()))?;
+        Ok(())
+    }
+
+    /// The symbol's guess, if it doesn't need to be fetched again.
+    pub fn get(&self, symbol : &str, clock : &Clock, ttl : Duration) -> Option<&EarningsGuess> {
+        self.entries.get(symbol)
+            .filter(|entry| is_fresh(entry, clock, ttl))
+            .map(|entry| &entry.guess)
+    }
+
+    pub fn insert(&mut self, symbol : &str, guess : EarningsGuess, fetched : DateTime<Utc>) {
+        self.entries.insert(symbol.to_string(), Entry{
+            fetched: fetched,
+            guess: guess,
+        });
+    }
+
+    /// Returns false if the symbol wasn't cached.
+    pub fn remove(&mut self, symbol : &str) -> bool {
+        self.entries.remove(symbol).is_some()
+    }
+
+    /// Remove every guess that would be fetched again anyway, returning their symbols.
+    pub fn prune(&mut self, clock : &Clock, ttl : Duration) -> Vec<String> {
+        let stale = self.entries.iter()
+            .filter(|&(_, entry)| !is_fresh(entry, clock, ttl))
+            .map(|(symbol, _)| symbol.clone())
+            .collect::<Vec<_>>();
+
+        for symbol in stale.iter() {
+            self.entries.remove(symbol);
+        }
+
+        stale
+    }
+}
+
+/// An entry is fresh if it was fetched within the TTL and its earnings haven't passed. Guesses cached before
+/// confidence scoring was added don't have one, so they're stale too.
+pub fn is_fresh(entry : &Entry, clock : &Clock, ttl : Duration) -> bool {
+    clock.now().signed_duration_since(entry.fetched) < ttl &&
+        entry.guess.last_session >= clock.today() - Duration::days(STALE_DAYS) &&
+        entry.guess.confidence > 0.0
+}
+
+/// `earnings-trade-scheduler/earnings_cache.json` in the XDG data directory, which is `~/.local/share` unless
+/// `XDG_DATA_HOME` is set. If neither that nor `HOME` is set, it's the old location in the working directory.
+pub fn default_path() -> PathBuf {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share")));
+
+    match data_home {
+        Some(dir) => dir.join("earnings-trade-scheduler").join(FILE_NAME),
+        None => PathBuf::from(LEGACY_PATH),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use earnings::Date;
+    use testing::{logger, temp_path};
+
+    #[test]
+    fn prune_removes_stale_guesses() {
+        let clock = Clock::AsOf(Date::from_ymd(2018, 10, 15));
+        let ttl = Duration::days(7);
+        let fetched = clock.now() - Duration::days(1);
+
+        let mut cache = EarningsCache::default();
+        cache.insert("AAPL", EarningsGuess::sample(Date::from_ymd(2018, 10, 29)).with_confidence(0.75), fetched);
+        cache.insert("MSFT", EarningsGuess::sample(Date::from_ymd(2018, 10, 10)), fetched);
+        cache.insert("AMZN", EarningsGuess::sample(Date::from_ymd(2018, 10, 25)).with_confidence(0.0), fetched);
+        cache.insert("GOOG", EarningsGuess::sample(Date::from_ymd(2018, 10, 25)), clock.now() - Duration::days(8));
+
+        assert!(cache.get("AAPL", &clock, ttl).is_some());
+        assert!(cache.get("MSFT", &clock, ttl).is_none());
+        assert!(cache.get("GOOG", &clock, Duration::days(10)).is_some());
+
+        assert_eq!(cache.prune(&clock, ttl), vec!["AMZN", "GOOG", "MSFT"]);
+        assert_eq!(cache.entries.keys().collect::<Vec<_>>(), vec!["AAPL"]);
+    }
+
+    #[test]
+    fn loads_version_1() {
+        let path = temp_path("earnings-cache.json");
+        {
+            let mut file = File::create(&path).unwrap();
+            write!(file, r#"{{"AAPL": {}, "MSFT": {{"last_session": "garbage"}}}}"#,
+                serde_json::to_string(&EarningsGuess::sample(Date::from_ymd(2018, 10, 29)).with_confidence(0.75)).unwrap()).unwrap();
+        }
+
+        let logger = logger();
+        let cache = EarningsCache::load(&logger, &path).unwrap();
+        assert_eq!(cache.entries.keys().collect::<Vec<_>>(), vec!["AAPL"]);
+        assert_eq!(cache.entries["AAPL"].guess.last_session, Date::from_ymd(2018, 10, 29));
+
+        cache.save(&path).unwrap();
+        let reloaded = EarningsCache::load(&logger, &path).unwrap();
+        assert_eq!(reloaded.entries["AAPL"].fetched, cache.entries["AAPL"].fetched);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
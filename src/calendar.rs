@@ -0,0 +1,305 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::sync::RwLock;
+use failure::{Error, ResultExt};
+use chrono::{Datelike, Duration, NaiveTime, Weekday};
+use earnings::Date;
+
+/// Market closures that don't follow any rule.
+static ONE_OFF_CLOSURES : &[(i32, u32, u32)] = &[
+    // September 11
+    (2001, 9, 11),
+    (2001, 9, 12),
+    (2001, 9, 13),
+    (2001, 9, 14),
+    // National days of mourning for former presidents
+    (2004, 6, 11),
+    (2007, 1, 2),
+    (2018, 12, 5),
+    (2025, 1, 9),
+    // Hurricane Sandy
+    (2012, 10, 29),
+    (2012, 10, 30),
+];
+
+lazy_static! {
+    static ref CALENDAR : RwLock<TradingCalendar> = RwLock::new(TradingCalendar::new());
+}
+
+/// The NYSE/Nasdaq holiday and early close schedule.
+#[derive(Debug, Clone, Default)]
+pub struct TradingCalendar {
+    extra_closures : BTreeSet<Date>,
+    extra_early_closes : BTreeMap<Date, NaiveTime>,
+}
+
+impl TradingCalendar {
+    pub fn new() -> TradingCalendar {
+        TradingCalendar{
+            extra_closures: BTreeSet::new(),
+            extra_early_closes: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_closure(&mut self, date : Date) {
+        self.extra_closures.insert(date);
+    }
+
+    pub fn add_early_close(&mut self, date : Date, time : NaiveTime) {
+        self.extra_early_closes.insert(date, time);
+    }
+
+    /// Read extra closure dates from a file containing one YYYY-MM-DD date per line.
+    /// A date followed by a time (`2018-07-03 13:00`) is a shortened session closing at that time instead of a full closure.
+    /// Blank lines and anything after a `#` are ignored.
+    pub fn load_closures(&mut self, path : &str) -> Result<usize, Error> {
+        let file = File::open(path).with_context(|_| format!("opening {}", path))?;
+        let mut count = 0;
+        for (line_no, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let text = line.split('#').next().unwrap_or("").trim();
+            if text.is_empty() {
+                continue
+            }
+
+            let mut fields = text.split_whitespace();
+            let date_text = fields.next().unwrap_or("");
+            let date = Date::parse_from_str(date_text, "%Y-%m-%d")
+                .with_context(|_| format!("{} line {}: bad date `{}`", path, line_no + 1, date_text))?;
+
+            match fields.next() {
+                Some(time_text) => {
+                    let time = NaiveTime::parse_from_str(time_text, "%H:%M")
+                        .with_context(|_| format!("{} line {}: bad time `{}`", path, line_no + 1, time_text))?;
+                    self.add_early_close(date, time);
+                },
+                None => self.add_closure(date),
+            }
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    pub fn is_holiday(&self, date : Date) -> bool {
+        self.extra_closures.contains(&date) || is_rule_holiday(date)
+    }
+
+    pub fn is_trading_day(&self, date : Date) -> bool {
+        match date.weekday() {
+            Weekday::Sat | Weekday::Sun => false,
+            _ => !self.is_holiday(date),
+        }
+    }
+
+    /// If the session on this date ends before the usual 4:00 PM close, return the closing time.
+    pub fn early_close(&self, date : Date) -> Option<NaiveTime> {
+        if !self.is_trading_day(date) {
+            return None
+        }
+
+        self.extra_early_closes.get(&date)
+            .cloned()
+            .or_else(|| if is_rule_early_close(date) { Some(NaiveTime::from_hms(13, 0, 0)) } else { None })
+    }
+}
+
+/// Check the global calendar.
+pub fn is_trading_day(date : Date) -> bool {
+    CALENDAR.read().unwrap().is_trading_day(date)
+}
+
+/// Check the global calendar.
+pub fn early_close(date : Date) -> Option<NaiveTime> {
+    CALENDAR.read().unwrap().early_close(date)
+}
+
+/// Add the closures from `path` to the global calendar.
+pub fn load_closures(path : &str) -> Result<usize, Error> {
+    CALENDAR.write().unwrap().load_closures(path)
+}
+
+fn nth_weekday(year : i32, month : u32, weekday : Weekday, n : u32) -> Date {
+    let first = Date::from_ymd(year, month, 1);
+    let offset = (7 + weekday.num_days_from_monday() - first.weekday().num_days_from_monday()) % 7;
+    first + Duration::days((offset + 7 * (n - 1)) as i64)
+}
+
+fn last_weekday(year : i32, month : u32, weekday : Weekday) -> Date {
+    let last = if month == 12 { Date::from_ymd(year, 12, 31) } else { Date::from_ymd(year, month + 1, 1).pred() };
+    let offset = (7 + last.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+    last - Duration::days(offset as i64)
+}
+
+/// Easter Sunday, using the anonymous Gregorian computus.
+fn easter(year : i32) -> Date {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    Date::from_ymd(year, month as u32, day as u32)
+}
+
+/// Fixed-date holidays move to Friday when they fall on Saturday and Monday when they fall on Sunday.
+fn observed(date : Date) -> Date {
+    match date.weekday() {
+        Weekday::Sat => date.pred(),
+        Weekday::Sun => date.succ(),
+        _ => date,
+    }
+}
+
+fn is_rule_holiday(date : Date) -> bool {
+    let year = date.year();
+
+    // New Year's Day is not moved back to Friday when it falls on a Saturday.
+    let new_years = Date::from_ymd(year, 1, 1);
+    if new_years.weekday() != Weekday::Sat && date == observed(new_years) {
+        return true
+    }
+
+    let mut holidays = vec![
+        nth_weekday(year, 1, Weekday::Mon, 3), // Martin Luther King Jr. Day
+        nth_weekday(year, 2, Weekday::Mon, 3), // Presidents' Day
+        easter(year) - Duration::days(2), // Good Friday
+        last_weekday(year, 5, Weekday::Mon), // Memorial Day
+        observed(Date::from_ymd(year, 7, 4)), // Independence Day
+        nth_weekday(year, 9, Weekday::Mon, 1), // Labor Day
+        nth_weekday(year, 11, Weekday::Thu, 4), // Thanksgiving
+        observed(Date::from_ymd(year, 12, 25)), // Christmas
+    ];
+
+    if year >= 2022 {
+        holidays.push(observed(Date::from_ymd(year, 6, 19))); // Juneteenth
+    }
+
+    holidays.contains(&date) ||
+        ONE_OFF_CLOSURES.iter().any(|&(y, m, d)| date == Date::from_ymd(y, m, d))
+}
+
+/// The 1:00 PM sessions: July 3rd, the day after Thanksgiving, and Christmas Eve.
+/// Callers are responsible for checking that the date is a trading day at all.
+fn is_rule_early_close(date : Date) -> bool {
+    let year = date.year();
+    date == Date::from_ymd(year, 7, 3) ||
+        date == nth_weekday(year, 11, Weekday::Thu, 4).succ() ||
+        date == Date::from_ymd(year, 12, 24)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use testing::temp_path;
+
+    fn ymd(year : i32, month : u32, day : u32) -> Date {
+        Date::from_ymd(year, month, day)
+    }
+
+    #[test]
+    fn weekday_rules() {
+        assert_eq!(nth_weekday(2018, 1, Weekday::Mon, 3), ymd(2018, 1, 15));
+        assert_eq!(nth_weekday(2018, 11, Weekday::Thu, 4), ymd(2018, 11, 22));
+        assert_eq!(last_weekday(2018, 5, Weekday::Mon), ymd(2018, 5, 28));
+        assert_eq!(last_weekday(2018, 12, Weekday::Mon), ymd(2018, 12, 31));
+        assert_eq!(last_weekday(2019, 12, Weekday::Fri), ymd(2019, 12, 27));
+    }
+
+    #[test]
+    fn easter_sundays() {
+        assert_eq!(easter(2018), ymd(2018, 4, 1));
+        assert_eq!(easter(2019), ymd(2019, 4, 21));
+        assert_eq!(easter(2024), ymd(2024, 3, 31));
+        assert_eq!(easter(2025), ymd(2025, 4, 20));
+    }
+
+    #[test]
+    fn nyse_holidays() {
+        let calendar = TradingCalendar::new();
+        let closed = [
+            ymd(2018, 3, 30), // Good Friday
+            ymd(2017, 1, 2), // New Year's Day, observed on Monday
+            ymd(2020, 7, 3), // Independence Day, observed on Friday
+            ymd(2021, 12, 24), // Christmas, observed on Friday
+            ymd(2022, 6, 20), // Juneteenth, observed on Monday
+            ymd(2023, 6, 19), // Juneteenth
+            ymd(2018, 12, 5), // President Bush's funeral
+            ymd(2012, 10, 30), // Hurricane Sandy
+        ];
+        for &date in closed.iter() {
+            assert!(!calendar.is_trading_day(date), "{} should be closed", date);
+        }
+
+        let open = [
+            ymd(2021, 12, 31), // New Year's Day on a Saturday isn't moved back
+            ymd(2021, 6, 18), // No Juneteenth before 2022
+            ymd(2018, 11, 12), // Veterans Day
+            ymd(2018, 11, 23), // The day after Thanksgiving
+        ];
+        for &date in open.iter() {
+            assert!(calendar.is_trading_day(date), "{} should be open", date);
+        }
+
+        assert!(!calendar.is_trading_day(ymd(2018, 10, 27)));
+    }
+
+    #[test]
+    fn early_closes() {
+        let mut calendar = TradingCalendar::new();
+        let one_pm = Some(NaiveTime::from_hms(13, 0, 0));
+        let sessions = [
+            (ymd(2018, 7, 3), one_pm),
+            (ymd(2023, 7, 3), one_pm),
+            (ymd(2018, 11, 23), one_pm), // The day after Thanksgiving
+            (ymd(2018, 12, 24), one_pm),
+            (ymd(2018, 7, 2), None),
+            (ymd(2018, 12, 26), None),
+            // July 3rd on a weekend doesn't shorten the Friday before.
+            (ymd(2021, 7, 2), None),
+            // Independence Day observed on Friday the 3rd.
+            (ymd(2020, 7, 3), None),
+            (ymd(2020, 7, 2), None),
+            // Christmas observed on Friday the 24th.
+            (ymd(2021, 12, 24), None),
+            (ymd(2021, 12, 23), None),
+            (ymd(2022, 12, 24), None),
+        ];
+        for &(date, close) in sessions.iter() {
+            assert_eq!(calendar.early_close(date), close, "{}", date);
+        }
+
+        calendar.add_early_close(ymd(2018, 12, 24), NaiveTime::from_hms(12, 0, 0));
+        assert_eq!(calendar.early_close(ymd(2018, 12, 24)), Some(NaiveTime::from_hms(12, 0, 0)));
+    }
+
+    #[test]
+    fn loads_closures() {
+        let path = temp_path("closures.txt");
+        fs::write(&path, "# Unscheduled closures\n2026-03-02\n\n2026-03-03 13:30  # Half day\n").unwrap();
+
+        let mut calendar = TradingCalendar::new();
+        let count = calendar.load_closures(path.to_str().unwrap()).unwrap();
+        assert_eq!(count, 2);
+        assert!(!calendar.is_trading_day(ymd(2026, 3, 2)));
+        assert!(calendar.is_trading_day(ymd(2026, 3, 3)));
+        assert_eq!(calendar.early_close(ymd(2026, 3, 3)), Some(NaiveTime::from_hms(13, 30, 0)));
+
+        fs::write(&path, "2026-03-02\n2026-13-01\n").unwrap();
+        let mut calendar = TradingCalendar::new();
+        let error = calendar.load_closures(path.to_str().unwrap()).unwrap_err();
+        assert!(error.to_string().ends_with("line 2: bad date `2026-13-01`"), "{}", error);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
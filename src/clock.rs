@@ -0,0 +1,27 @@
+use chrono::{self, DateTime, Utc};
+use earnings::Date;
+
+/// Where the current date comes from when deciding which earnings dates are still upcoming.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    /// The system clock.
+    System,
+    /// Pretend it's the start of this day, from `--as-of`.
+    AsOf(Date),
+}
+
+impl Clock {
+    pub fn today(&self) -> Date {
+        match *self {
+            Clock::System => chrono::Local::today().naive_local(),
+            Clock::AsOf(date) => date,
+        }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        match *self {
+            Clock::System => Utc::now(),
+            Clock::AsOf(date) => DateTime::from_utc(date.and_hms(0, 0, 0), Utc),
+        }
+    }
+}
@@ -1,155 +1,264 @@
-use failure::{Error, ResultExt, err_msg};
+use failure::{Error, Fail, err_msg};
 use earnings::{Date, DatelikeExt, EarningsDateTime, AnnounceTime};
 use chrono::{Datelike, Duration, Weekday};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json;
+use regex::Regex;
+use ranking::Ranking;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::str::FromStr;
+use std::sync::RwLock;
 use std::collections::HashMap;
 
-#[derive(Debug,Deserialize,Serialize,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
-pub enum Strategy {
-    #[serde(rename="call_3d_preearnings")]
-    Call3DaysBeforeEarnings,
+/// How the open and close dates are counted from the earnings date.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum DayCount {
+    /// Count trading sessions, the same way CML's backtests do.
+    Trading,
+    /// The original calendar-day offsets, for comparison.
+    Calendar,
+}
 
-    #[serde(rename="call_7d_preearnings")]
-    Call7DaysBeforeEarnings,
+impl FromStr for DayCount {
+    type Err = Error;
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s {
+            "trading" => Ok(DayCount::Trading),
+            "calendar" => Ok(DayCount::Calendar),
+            _ => Err(err_msg(format!("Unknown day count {}, expected trading or calendar", s))),
+        }
+    }
+}
 
-    #[serde(rename="call_14d_preearnings")]
-    Call14DaysBeforeEarnings,
+/// Whether a strategy trades ahead of the earnings announcement or after it.
+#[derive(Debug,Deserialize,Serialize,Clone,Copy,PartialEq,Eq)]
+#[serde(rename_all="lowercase")]
+pub enum Phase {
+    Pre,
+    Post,
+}
 
-    #[serde(rename="strangle_4d_preearnings")]
-    Strangle4DaysBeforeEarnings,
+/// How a calendar-day offset is turned into a trading day.
+#[derive(Debug,Deserialize,Serialize,Clone,Copy,PartialEq,Eq,Default)]
+#[serde(rename_all="lowercase")]
+pub enum Rounding {
+    /// Step back to the closest trading day.
+    #[default]
+    Previous,
+    /// Step forward to the next trading day.
+    Next,
+    /// Count the days as weekdays, then step back to the closest trading day.
+    Weekdays,
+}
 
-    #[serde(rename="strangle_7d_preearnings")]
-    Strangle7DaysBeforeEarnings,
+/// Where an open or close falls relative to the last pre-earnings session.
+#[derive(Debug,Deserialize,Serialize,Clone)]
+pub struct Offset {
+    /// Trading sessions, used with `DayCount::Trading`.
+    pub sessions : i64,
+    /// Calendar days, used with `DayCount::Calendar`.
+    pub days : i64,
+    #[serde(default)]
+    pub rounding : Rounding,
+}
 
-    #[serde(rename="strangle_14d_preearnings")]
-    Strangle14DaysBeforeEarnings,
+impl Offset {
+    fn apply(&self, last_preearnings_session : Date, day_count : DayCount) -> Date {
+        match day_count {
+            DayCount::Trading => last_preearnings_session.add_trading_days(self.sessions),
+            DayCount::Calendar => {
+                match self.rounding {
+                    Rounding::Previous => (last_preearnings_session + Duration::days(self.days)).closest_trading_day(),
+                    Rounding::Next => {
+                        let date = last_preearnings_session + Duration::days(self.days);
+                        if date.is_trading_day() { date } else { date.next_trading_day() }
+                    },
+                    Rounding::Weekdays => add_weekdays(last_preearnings_session, self.days).closest_trading_day(),
+                }
+            }
+        }
+    }
+}
+
+fn add_weekdays(date : Date, days : i64) -> Date {
+    let step = if days < 0 { -1 } else { 1 };
+    let mut date = date;
+    let mut remaining = days.abs();
+    while remaining > 0 {
+        date += Duration::days(step);
+        match date.weekday() {
+            Weekday::Sat | Weekday::Sun => (),
+            _ => remaining -= 1,
+        }
+    }
+    date
+}
 
-    #[serde(rename="put_spread_post_earnings")]
-    PutSpreadAfterEarnings,
+/// A CML strategy, as defined in the strategies file.
+#[derive(Debug,Deserialize,Serialize,Clone)]
+pub struct StrategyDef {
+    /// The value used in the CSV `strategy` column and the `--strategy` option.
+    pub id : String,
+    pub name : String,
+    pub abbreviation : String,
+    pub phase : Phase,
+    pub open : Offset,
+    pub close : Offset,
+}
+
+static DEFAULT_STRATEGIES : &'static str = include_str!("default_strategies.json");
 
-    #[serde(rename="iron_condor_post_earnings")]
-    IronCondorAfterEarnings,
+lazy_static! {
+    static ref STRATEGIES : RwLock<Vec<&'static StrategyDef>> = RwLock::new(
+        serde_json::from_str::<Vec<StrategyDef>>(DEFAULT_STRATEGIES)
+            .expect("parsing default strategies")
+            .into_iter()
+            .map(|def| &*Box::leak(Box::new(def)))
+            .collect()
+    );
+}
 
-    #[serde(rename="long_straddle_post_earnings")]
-    LongStraddleAfterEarnings,
+/// Add strategy definitions to the built-in set. A definition with the same id as an existing strategy replaces it.
+/// This should happen before any `Strategy` values are created, since they refer to the set by position.
+pub fn register_strategies(defs : Vec<StrategyDef>) -> Result<(), Error> {
+    let mut strategies = STRATEGIES.write().unwrap();
+    for def in defs {
+        if def.id.is_empty() {
+            return Err(err_msg("Strategy definitions must have an id"));
+        }
 
-    #[serde(rename="long_call_post_earnings")]
-    LongCallAfterEarnings,
+        let def : &'static StrategyDef = Box::leak(Box::new(def));
+        match strategies.iter().position(|existing| existing.id == def.id) {
+            Some(index) => strategies[index] = def,
+            None => strategies.push(def),
+        }
+    }
 
-    #[serde(rename="long_put_post_earnings")]
-    LongPutAfterEarnings,
+    Ok(())
+}
+
+/// A handle to one of the registered strategy definitions.
+#[derive(Clone,Copy)]
+pub struct Strategy {
+    index : usize,
+    def : &'static StrategyDef,
+}
+
+impl PartialEq for Strategy {
+    fn eq(&self, other : &Strategy) -> bool { self.index == other.index }
+}
+
+impl Eq for Strategy {}
+
+impl PartialOrd for Strategy {
+    fn partial_cmp(&self, other : &Strategy) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Strategy {
+    fn cmp(&self, other : &Strategy) -> Ordering { self.index.cmp(&other.index) }
+}
+
+impl Hash for Strategy {
+    fn hash<H : Hasher>(&self, state : &mut H) { self.index.hash(state) }
+}
+
+impl fmt::Debug for Strategy {
+    fn fmt(&self, f : &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Strategy({})", self.def.id)
+    }
 }
 
 impl FromStr for Strategy {
     type Err = Error;
     fn from_str(s : &str) -> Result<Self, Self::Err> {
-        match s {
-            "call_3d_preearnings" => Ok(Strategy::Call3DaysBeforeEarnings),
-            "call_7d_preearnings" => Ok(Strategy::Call7DaysBeforeEarnings),
-            "call_14d_preearnings" => Ok(Strategy::Call14DaysBeforeEarnings),
-            "strangle_4d_preearnings" => Ok(Strategy::Strangle4DaysBeforeEarnings),
-            "strangle_7d_preearnings" => Ok(Strategy::Strangle7DaysBeforeEarnings),
-            "strangle_14d_preearnings" => Ok(Strategy::Strangle14DaysBeforeEarnings),
-            "put_spread_post_earnings" => Ok(Strategy::PutSpreadAfterEarnings),
-            "iron_condor_post_earnings" => Ok(Strategy::IronCondorAfterEarnings),
-            "long_straddle_post_earnings" => Ok(Strategy::LongStraddleAfterEarnings),
-            "long_call_post_earnings" => Ok(Strategy::LongCallAfterEarnings),
-            "long_put_post_earnings" => Ok(Strategy::LongPutAfterEarnings),
-            _ => Err(err_msg(format!("Unknown strategy {}", s))),
-        }
+        STRATEGIES.read().unwrap()
+            .iter()
+            .position(|def| def.id == s)
+            .map(|index| Strategy::from_index(index))
+            .ok_or_else(|| err_msg(format!("Unknown strategy {}", s)))
+    }
+}
+
+impl Serialize for Strategy {
+    fn serialize<S : Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.id())
+    }
+}
+
+impl<'de> Deserialize<'de> for Strategy {
+    fn deserialize<D : Deserializer<'de>>(deserializer : D) -> Result<Strategy, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        id.parse().map_err(|e : Error| de::Error::custom(e))
     }
 }
 
 impl Strategy {
+    fn from_index(index : usize) -> Strategy {
+        Strategy{
+            index: index,
+            def: STRATEGIES.read().unwrap()[index],
+        }
+    }
+
+    pub fn all() -> Vec<Strategy> {
+        let count = STRATEGIES.read().unwrap().len();
+        (0..count).map(Strategy::from_index).collect()
+    }
+
     pub fn preearnings_strategies() -> Vec<Strategy> {
-        vec![Strategy::Call3DaysBeforeEarnings, Strategy::Call7DaysBeforeEarnings, Strategy::Call14DaysBeforeEarnings, Strategy::Strangle4DaysBeforeEarnings, Strategy::Strangle7DaysBeforeEarnings, Strategy::Strangle14DaysBeforeEarnings]
+        Strategy::all().into_iter().filter(|s| s.phase() == Phase::Pre).collect()
     }
 
     pub fn postearnings_strategies() -> Vec<Strategy> {
-        vec![Strategy::PutSpreadAfterEarnings, Strategy::IronCondorAfterEarnings, Strategy::LongStraddleAfterEarnings, Strategy::LongCallAfterEarnings, Strategy::LongPutAfterEarnings]
-    }
-
-    pub fn open_date(&self, last_preearnings_session : Date) -> Date {
-        match *self {
-            Strategy::Call3DaysBeforeEarnings => {
-                let delta = match last_preearnings_session.weekday() {
-                    Weekday::Mon | Weekday::Tue | Weekday::Wed => 5,
-                    _ => 3
-                };
-
-                last_preearnings_session - Duration::days(delta)
-            },
-            Strategy::Call7DaysBeforeEarnings => last_preearnings_session - Duration::days(7),
-            Strategy::Call14DaysBeforeEarnings => last_preearnings_session - Duration::days(14),
-            Strategy::Strangle4DaysBeforeEarnings => {
-                let delta = match last_preearnings_session.weekday() {
-                    Weekday::Mon | Weekday::Tue | Weekday::Wed | Weekday::Thu => 6,
-                    _ => 4,
-                };
-
-                last_preearnings_session - Duration::days(delta)
-            },
-            Strategy::Strangle7DaysBeforeEarnings => last_preearnings_session - Duration::days(7),
-            Strategy::Strangle14DaysBeforeEarnings => last_preearnings_session - Duration::days(14),
-            Strategy::PutSpreadAfterEarnings
-            | Strategy::IronCondorAfterEarnings
-            | Strategy::LongStraddleAfterEarnings
-            | Strategy::LongCallAfterEarnings
-            | Strategy::LongPutAfterEarnings => last_preearnings_session.next_trading_day(),
-        }
+        Strategy::all().into_iter().filter(|s| s.phase() == Phase::Post).collect()
     }
 
-    pub fn close_date(&self, last_preearnings_session : Date) -> Date {
-        let close = match *self {
-            Strategy::Call3DaysBeforeEarnings => last_preearnings_session,
-            Strategy::Call7DaysBeforeEarnings => last_preearnings_session,
-            Strategy::Call14DaysBeforeEarnings => last_preearnings_session,
-            Strategy::Strangle4DaysBeforeEarnings => last_preearnings_session,
-            Strategy::Strangle7DaysBeforeEarnings => last_preearnings_session,
-            Strategy::Strangle14DaysBeforeEarnings => last_preearnings_session,
-            Strategy::PutSpreadAfterEarnings => last_preearnings_session + Duration::days(22),
-            Strategy::IronCondorAfterEarnings => last_preearnings_session + Duration::days(32),
-            Strategy::LongStraddleAfterEarnings => last_preearnings_session + Duration::days(8),
-            Strategy::LongCallAfterEarnings => last_preearnings_session + Duration::days(15),
-            Strategy::LongPutAfterEarnings => last_preearnings_session + Duration::days(15),
-        };
-
-        close.closest_trading_day()
-    }
-
-    pub fn short_name(&self) -> &'static str {
-        match *self {
-            Strategy::Call3DaysBeforeEarnings => "E-3 Call",
-            Strategy::Call7DaysBeforeEarnings => "E-7 Call",
-            Strategy::Call14DaysBeforeEarnings => "E-14 Call",
-            Strategy::Strangle4DaysBeforeEarnings => "E-4 Strangle",
-            Strategy::Strangle7DaysBeforeEarnings => "E-7 Strangle",
-            Strategy::Strangle14DaysBeforeEarnings => "E-14 Strangle",
-            Strategy::IronCondorAfterEarnings => "E+1 Iron Condor",
-            Strategy::PutSpreadAfterEarnings => "E+1 Put Spread",
-            Strategy::LongStraddleAfterEarnings => "E+1 Long Straddle",
-            Strategy::LongCallAfterEarnings => "E+1 Long Call (price +3%)",
-            Strategy::LongPutAfterEarnings => "E+1 Long Put (price -3%)",
-        }
+    pub fn id(&self) -> &'static str { &self.def.id }
+
+    pub fn phase(&self) -> Phase { self.def.phase }
+
+    pub fn open_date(&self, last_preearnings_session : Date, day_count : DayCount) -> Date {
+        self.def.open.apply(last_preearnings_session, day_count)
     }
 
-    pub fn abbreviation(&self) -> &str {
-        match *self {
-            Strategy::Call3DaysBeforeEarnings => "E-3C",
-            Strategy::Call7DaysBeforeEarnings => "E-7C",
-            Strategy::Call14DaysBeforeEarnings => "E-14C",
-            Strategy::Strangle4DaysBeforeEarnings => "E-4S",
-            Strategy::Strangle7DaysBeforeEarnings => "E-7S",
-            Strategy::Strangle14DaysBeforeEarnings => "E-14S",
-            Strategy::IronCondorAfterEarnings => "E+1IC",
-            Strategy::PutSpreadAfterEarnings => "E+1P",
-            Strategy::LongStraddleAfterEarnings => "E+1LS",
-            Strategy::LongCallAfterEarnings => "E+1LC",
-            Strategy::LongPutAfterEarnings => "E+1LP",
+    pub fn close_date(&self, last_preearnings_session : Date, day_count : DayCount) -> Date {
+        self.def.close.apply(last_preearnings_session, day_count)
+    }
+
+    pub fn short_name(&self) -> &'static str { &self.def.name }
+
+    pub fn abbreviation(&self) -> &'static str { &self.def.abbreviation }
+}
+
+/// The name of the earnings date from the backtest results when it's weighed against the other sources.
+pub static SOURCE_NAME : &'static str = "CML";
+
+/// A field of a `BacktestResultInput` that couldn't be parsed.
+#[derive(Debug)]
+pub struct FieldError {
+    pub field : &'static str,
+    pub message : String,
+}
+
+impl FieldError {
+    pub fn new(field : &'static str, message : String) -> FieldError {
+        FieldError{
+            field: field,
+            message: message,
         }
     }
 }
 
+impl fmt::Display for FieldError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.field, self.message)
+    }
+}
+
+impl Fail for FieldError {}
+
 #[derive(Debug,Deserialize)]
 pub struct BacktestResultInput {
     pub symbol : String,
@@ -161,10 +270,12 @@ pub struct BacktestResultInput {
     pub backtest_length : usize,
     pub next_earnings : String,
     pub prev_earnings_result : String,
-    pub strategy : Strategy,
+    /// Inputs exported for a single strategy can leave this out and have it inferred from the file name.
+    #[serde(default)]
+    pub strategy : Option<Strategy>,
 }
 
-#[derive(Debug,Clone,Serialize)]
+#[derive(Debug,Clone,Serialize,Deserialize)]
 pub struct BacktestResult {
     pub symbol : String,
     pub wins : usize,
@@ -179,38 +290,47 @@ pub struct BacktestResult {
 }
 
 impl BacktestResult {
-    #[inline]
-    pub fn sort_key(&self) -> isize { (self.avg_trade_return * (self.wins + self.losses) as i32) as isize }
-
     pub fn stats(&self) -> String {
         format!("({avg_return}%,{wins}/{losses})", avg_return=self.avg_trade_return, wins=self.wins, losses=self.losses)
     }
 
+    /// The date of the last earnings announcement, if `prev_earnings_result` has one.
+    pub fn prev_earnings_date(&self) -> Option<Date> {
+        lazy_static! {
+            static ref RE : Regex = Regex::new(r#"\d{4}[-‑]\d{2}[-‑]\d{2}"#).unwrap();
+        }
+
+        RE.find(&self.prev_earnings_result)
+            .and_then(|m| Date::parse_from_str(&m.as_str().replace('‑', "-"), "%Y-%m-%d").ok())
+    }
+
     pub fn from_input(input : BacktestResultInput) -> Result<BacktestResult, Error> {
-        let earnings_str = input.next_earnings.replace("Not Verified", "");
-        let earnings_date = Date::parse_from_str(earnings_str.as_str(), "%Y‑%m‑%d")
+        let strategy = input.strategy.ok_or_else(|| FieldError::new("strategy", "missing".to_string()))?;
+        // TradeMachine writes dates with non-breaking hyphens, but they may have been replaced with plain ones.
+        let earnings_str = input.next_earnings.replace("Not Verified", "").replace('‑', "-");
+        let earnings_date = Date::parse_from_str(earnings_str.trim(), "%Y-%m-%d")
             .map(|d| EarningsDateTime{date: d, time: AnnounceTime::Unknown})
-            .with_context(|e| format!("next_earnings `{}` {}", input.next_earnings, e))?;
+            .map_err(|e| FieldError::new("next_earnings", format!("`{}` {}", input.next_earnings, e)))?;
 
         let win_rate = input.win_rate.chars()
             .take_while(|x| x.is_digit(10) || *x == '‑' || *x == '-') // These are actually two different dash characters
             .map(|x| if x == '‑' { '-' } else {x})
             .collect::<String>()
             .parse::<i32>()
-            .with_context(|e| format!("win_rate {} : {}", input.win_rate, e))?;
+            .map_err(|e| FieldError::new("win_rate", format!("{} : {}", input.win_rate, e)))?;
 
         let avg_trade_return = input.avg_trade_return.chars()
             .take_while(|x| x.is_digit(10) || *x == '‑' || *x == '-')
             .map(|x| if x == '‑' { '-' } else {x})
             .collect::<String>()
             .parse::<i32>()
-            .with_context(|e| format!("avg_trade_return {} : {}", input.avg_trade_return, e))?;
+            .map_err(|e| FieldError::new("avg_trade_return", format!("{} : {}", input.avg_trade_return, e)))?;
 
         let total_return = input.total_return.chars()
             .take_while(|x| x.is_digit(10) || *x == '‑' || *x == '-')
             .map(|x| if x == '‑' { '-' } else {x})
             .collect::<String>().parse::<i32>()
-            .with_context(|e| format!("total_return {} : {}", input.total_return, e))?;
+            .map_err(|e| FieldError::new("total_return", format!("{} : {}", input.total_return, e)))?;
 
         Ok(BacktestResult{
             symbol: input.symbol,
@@ -222,30 +342,123 @@ impl BacktestResult {
             backtest_length: input.backtest_length,
             next_earnings: earnings_date,
             prev_earnings_result: input.prev_earnings_result,
-            strategy: input.strategy,
+            strategy: strategy,
         })
     }
 }
 
-pub fn get_best_test(tests : &[BacktestResult]) -> usize {
+pub fn get_best_test(tests : &[BacktestResult], ranking : Ranking) -> usize {
     tests.iter()
         .enumerate()
-        .max_by_key(|&(_, x)| x.sort_key())
+        .max_by_key(|&(_, x)| ranking.score(x))
         .map(|x| x.0)
         .unwrap_or(0)
 }
 
-pub fn get_best_test_per_strategy(tests : &[BacktestResult]) -> HashMap<Strategy, usize> {
+pub fn get_best_test_per_strategy(tests : &[BacktestResult], ranking : Ranking) -> HashMap<Strategy, usize> {
     tests.iter()
         .enumerate()
         .fold(HashMap::new(), |mut acc, (index, test)| {
             {
                 let value = acc.entry(test.strategy).or_insert(index);
-                if tests[*value].sort_key() < test.sort_key() {
+                if ranking.score(&tests[*value]) < ranking.score(test) {
                     *value = index;
                 }
             }
 
             acc
         })
-}
\ No newline at end of file
+}
+
+/// Backtests for tests in any module. Each starts as a typical one and has whatever the test cares about changed.
+#[cfg(test)]
+impl BacktestResult {
+    /// AAPL, 10 wins and 2 losses averaging 25% over 3 years, with earnings on 2018-10-29 after 2018-07-31.
+    pub fn sample(strategy : &str) -> BacktestResult {
+        BacktestResult{
+            symbol: "AAPL".to_string(),
+            wins: 10,
+            losses: 2,
+            win_rate: 83,
+            avg_trade_return: 25,
+            total_return: 300,
+            backtest_length: 3,
+            next_earnings: EarningsDateTime{ date: Date::from_ymd(2018, 10, 29), time: AnnounceTime::Unknown },
+            prev_earnings_result: "2018-07-31".to_string(),
+            strategy: strategy.parse().unwrap(),
+        }
+    }
+
+    pub fn with_symbol(mut self, symbol : &str) -> BacktestResult {
+        self.symbol = symbol.to_string();
+        self
+    }
+
+    /// Sets the win rate to match.
+    pub fn with_trades(mut self, wins : usize, losses : usize) -> BacktestResult {
+        self.wins = wins;
+        self.losses = losses;
+        self.win_rate = (100 * wins / (wins + losses)) as i32;
+        self
+    }
+
+    pub fn with_returns(mut self, avg_trade_return : i32, total_return : i32) -> BacktestResult {
+        self.avg_trade_return = avg_trade_return;
+        self.total_return = total_return;
+        self
+    }
+
+    pub fn with_length(mut self, backtest_length : usize) -> BacktestResult {
+        self.backtest_length = backtest_length;
+        self
+    }
+
+    pub fn with_earnings(mut self, date : Date) -> BacktestResult {
+        self.next_earnings.date = date;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strategy(id : &str) -> Strategy {
+        id.parse().unwrap()
+    }
+
+    #[test]
+    fn trading_sessions_skip_holidays() {
+        // Thanksgiving week, 2018.
+        let last_session = Date::from_ymd(2018, 11, 29);
+        assert_eq!(strategy("call_7d_preearnings").open_date(last_session, DayCount::Trading), Date::from_ymd(2018, 11, 19));
+        assert_eq!(strategy("call_14d_preearnings").open_date(last_session, DayCount::Trading), Date::from_ymd(2018, 11, 8));
+        assert_eq!(strategy("call_7d_preearnings").close_date(last_session, DayCount::Trading), last_session);
+
+        // The 16 sessions after earnings skip the closure on December 5.
+        let last_session = Date::from_ymd(2018, 11, 26);
+        let put_spread = strategy("put_spread_post_earnings");
+        assert_eq!(put_spread.open_date(last_session, DayCount::Trading), Date::from_ymd(2018, 11, 27));
+        assert_eq!(put_spread.close_date(last_session, DayCount::Trading), Date::from_ymd(2018, 12, 19));
+    }
+
+    /// Calendar days give the same dates as before trading sessions were counted.
+    #[test]
+    fn calendar_days_match_old_dates() {
+        let last_session = Date::from_ymd(2018, 11, 29);
+        // A week before is Thanksgiving, so it opens the day before.
+        assert_eq!(strategy("call_7d_preearnings").open_date(last_session, DayCount::Calendar), Date::from_ymd(2018, 11, 21));
+        assert_eq!(strategy("call_14d_preearnings").open_date(last_session, DayCount::Calendar), Date::from_ymd(2018, 11, 15));
+        // Three and four days before, counting weekends for the later weekdays.
+        assert_eq!(strategy("call_3d_preearnings").open_date(last_session, DayCount::Calendar), Date::from_ymd(2018, 11, 26));
+        assert_eq!(strategy("strangle_4d_preearnings").open_date(last_session, DayCount::Calendar), Date::from_ymd(2018, 11, 23));
+
+        let last_session = Date::from_ymd(2018, 11, 26);
+        assert_eq!(strategy("call_3d_preearnings").open_date(last_session, DayCount::Calendar), Date::from_ymd(2018, 11, 21));
+        assert_eq!(strategy("strangle_4d_preearnings").open_date(last_session, DayCount::Calendar), Date::from_ymd(2018, 11, 20));
+
+        let put_spread = strategy("put_spread_post_earnings");
+        assert_eq!(put_spread.open_date(last_session, DayCount::Calendar), Date::from_ymd(2018, 11, 27));
+        assert_eq!(put_spread.close_date(last_session, DayCount::Calendar), Date::from_ymd(2018, 12, 18));
+    }
+}
@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+use std::fs::File;
+use failure::{Error, ResultExt};
+use serde_json;
+use cmlviz::StrategyDef;
+use earnings::SourceSettings;
+use filter::BacktestFilter;
+
+/// Settings loaded from the JSON file given with `--config`. Every section is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Strategy definitions to add to, or replace in, the built-in set.
+    pub strategies : Vec<StrategyDef>,
+
+    /// Earnings source settings, keyed by source name.
+    pub sources : HashMap<String, SourceSettings>,
+
+    /// Minimum backtest quality, keyed by `all`, `pre`, `post` or a strategy ID.
+    pub filters : HashMap<String, BacktestFilter>,
+
+    /// The order of the cells in TradeMachine JSON array rows, if it isn't the CSV column order.
+    pub json_columns : Option<Vec<String>>,
+}
+
+impl Settings {
+    pub fn load(path : &str) -> Result<Settings, Error> {
+        let file = File::open(path).with_context(|_| format!("opening {}", path))?;
+        let settings = serde_json::from_reader(file).with_context(|_| format!("parsing {}", path))?;
+        Ok(settings)
+    }
+}
@@ -0,0 +1,231 @@
+use std::fs::File;
+use std::path::Path;
+use failure::{Error, ResultExt};
+use scraper::Html;
+use serde_json::{self, Value};
+use cmlviz::{BacktestResultInput, FieldError};
+
+/// The columns of a result row, in the same order as the CSV. Array rows are read in this order unless the settings
+/// file gives another.
+static COLUMNS : &[&str] = &["symbol", "wins", "losses", "win_rate", "avg_trade_return", "total_return", "backtest_length",
+    "next_earnings", "prev_earnings_result", "strategy"];
+
+/// The columns a row can't be read without.
+static REQUIRED : &[&str] = &["symbol", "wins", "losses", "win_rate", "avg_trade_return", "total_return", "backtest_length",
+    "next_earnings"];
+
+pub fn default_columns() -> Vec<String> {
+    COLUMNS.iter().map(|column| column.to_string()).collect()
+}
+
+/// Check a column order from the settings file. An empty name is a column to skip, like a checkbox or a hidden ID.
+pub fn check_columns(columns : &[String]) -> Result<(), Error> {
+    for (index, column) in columns.iter().enumerate() {
+        if column.is_empty() {
+            continue
+        }
+        if !COLUMNS.contains(&column.as_str()) {
+            bail!("unknown column `{}`, expected one of {}", column, COLUMNS.join(", "));
+        }
+        if columns[..index].contains(column) {
+            bail!("column `{}` is listed twice", column);
+        }
+    }
+
+    if let Some(missing) = REQUIRED.iter().find(|&&required| !columns.iter().any(|column| column == required)) {
+        bail!("no `{}` column", missing);
+    }
+
+    Ok(())
+}
+
+/// A result row, with its cells as text, and the input read from them or why it couldn't be.
+#[derive(Debug)]
+pub struct Row {
+    pub cells : Vec<String>,
+    pub input : Result<BacktestResultInput, FieldError>,
+}
+
+/// Read the JSON behind a TradeMachine results table, as saved from the browser's network tab. Only a file that can't
+/// be read at all fails. Rows that can't be read are returned with their errors.
+pub fn read(path : &Path, columns : &[String]) -> Result<Vec<Row>, Error> {
+    let file = File::open(path).with_context(|_| format!("opening {}", path.display()))?;
+    let value = serde_json::from_reader(file).with_context(|_| format!("parsing {}", path.display()))?;
+    let rows = parse(value, columns).with_context(|_| format!("reading {}", path.display()))?;
+    Ok(rows)
+}
+
+/// The value can be the whole DataTables response, with the rows under `data` (or `aaData` in older versions), or just
+/// the rows. A row is either an array of cells in the order of `columns`, or an object keyed by the CSV column names.
+/// An array row with more cells than there are columns means the order is wrong, so it fails the whole file rather
+/// than reading every field from the wrong cell.
+pub fn parse(value : Value, columns : &[String]) -> Result<Vec<Row>, Error> {
+    let rows = match value {
+        Value::Array(rows) => rows,
+        Value::Object(mut response) => {
+            match response.remove("data").or_else(|| response.remove("aaData")) {
+                Some(Value::Array(rows)) => rows,
+                _ => bail!("no data array"),
+            }
+        },
+        _ => bail!("expected an object or an array"),
+    };
+
+    for (index, row) in rows.iter().enumerate() {
+        if let Value::Array(ref cells) = *row {
+            if cells.len() > columns.len() {
+                bail!("row {} has {} cells, but there are only {} columns. Set `json_columns` in the settings file to \
+                    match the table.", index + 1, cells.len(), columns.len());
+            }
+        }
+    }
+
+    let rows = rows.iter()
+        .map(|row| Row{
+            cells: COLUMNS.iter().map(|column| cell(row, columns, column).unwrap_or_default()).collect(),
+            input: parse_row(row, columns),
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+fn parse_row(row : &Value, columns : &[String]) -> Result<BacktestResultInput, FieldError> {
+    let strategy = match cell(row, columns, "strategy") {
+        Some(ref id) if !id.is_empty() => Some(id.parse().map_err(|e : Error| FieldError::new("strategy", e.to_string()))?),
+        _ => None,
+    };
+
+    Ok(BacktestResultInput{
+        symbol: required(row, columns, "symbol")?,
+        wins: number(row, columns, "wins")?,
+        losses: number(row, columns, "losses")?,
+        win_rate: required(row, columns, "win_rate")?,
+        avg_trade_return: required(row, columns, "avg_trade_return")?,
+        total_return: required(row, columns, "total_return")?,
+        backtest_length: number(row, columns, "backtest_length")?,
+        next_earnings: required(row, columns, "next_earnings")?,
+        prev_earnings_result: cell(row, columns, "prev_earnings_result").unwrap_or_default(),
+        strategy: strategy,
+    })
+}
+
+/// The text of a cell, without any HTML, and with non-breaking hyphens turned into plain ones.
+fn cell(row : &Value, columns : &[String], column : &str) -> Option<String> {
+    let value = match *row {
+        Value::Array(ref cells) => columns.iter().position(|c| c == column).and_then(|index| cells.get(index)),
+        Value::Object(ref cells) => cells.get(column),
+        _ => None,
+    };
+
+    value.and_then(|value| match *value {
+        Value::String(ref html) => {
+            let text = Html::parse_fragment(html).tree.nodes()
+                .filter_map(|node| node.value().as_text().map(|text| text.to_string()))
+                .collect::<String>();
+            Some(text.replace('‑', "-").trim().to_string())
+        },
+        Value::Number(ref n) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+fn required(row : &Value, columns : &[String], column : &'static str) -> Result<String, FieldError> {
+    cell(row, columns, column).ok_or_else(|| FieldError::new(column, "missing".to_string()))
+}
+
+fn number(row : &Value, columns : &[String], column : &'static str) -> Result<usize, FieldError> {
+    let text = required(row, columns, column)?;
+    text.parse::<usize>().map_err(|e| FieldError::new(column, format!("`{}` {}", text, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cmlviz::BacktestResult;
+
+    #[test]
+    fn array_rows() {
+        let value = serde_json::from_str(r#"{
+            "draw": 1,
+            "recordsTotal": 1,
+            "data": [
+                ["<a href=\"/symbol/AAPL\">AAPL</a>", "10", 2, "<span class=\"green\">83%</span>", "25%", "300%", "3",
+                 "2018‑10‑29 <i>Not Verified</i>", "2018‑07‑31"]
+            ]
+        }"#).unwrap();
+
+        let rows = parse(value, &default_columns()).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].cells[..3], ["AAPL", "10", "2"]);
+
+        let mut input = rows.into_iter().next().unwrap().input.unwrap();
+        assert_eq!(input.symbol, "AAPL");
+        assert_eq!(input.win_rate, "83%");
+        assert_eq!(input.next_earnings, "2018-10-29 Not Verified");
+        assert!(input.strategy.is_none());
+
+        input.strategy = Some("call_7d_preearnings".parse().unwrap());
+        let test = BacktestResult::from_input(input).unwrap();
+        assert_eq!(test.next_earnings.date, ::earnings::Date::from_ymd(2018, 10, 29));
+        assert_eq!((test.wins, test.losses, test.avg_trade_return), (10, 2, 25));
+    }
+
+    #[test]
+    fn object_rows() {
+        let value = serde_json::from_str(r#"[{
+            "symbol": "MSFT", "wins": 8, "losses": 4, "win_rate": "66%", "avg_trade_return": "-5%",
+            "total_return": "-40%", "backtest_length": 3, "next_earnings": "2018-10-24",
+            "strategy": "iron_condor_post_earnings"
+        }]"#).unwrap();
+
+        let rows = parse(value, &default_columns()).unwrap();
+        let input = rows[0].input.as_ref().unwrap();
+        assert_eq!(input.prev_earnings_result, "");
+        assert_eq!(input.strategy.unwrap().id(), "iron_condor_post_earnings");
+    }
+
+    #[test]
+    fn bad_rows() {
+        let value = serde_json::from_str(r#"[
+            { "symbol": "MSFT", "wins": "eight" },
+            { "symbol": "MSFT", "wins": 8, "losses": 4, "win_rate": "66%" },
+            { "symbol": "MSFT", "wins": 8, "losses": 4, "win_rate": "66%", "avg_trade_return": "-5%",
+              "total_return": "-40%", "backtest_length": 3, "next_earnings": "2018-10-24", "strategy": "no_such" }
+        ]"#).unwrap();
+
+        let errors = parse(value, &default_columns()).unwrap().into_iter()
+            .map(|row| row.input.unwrap_err().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(errors, vec!["wins `eight` invalid digit found in string", "avg_trade_return missing",
+            "strategy Unknown strategy no_such"]);
+    }
+
+    #[test]
+    fn configured_columns() {
+        let row = r#"[["<input type=\"checkbox\">", "AAPL", "10", "2", "83%", "25%", "300%", "3", "2018-10-29", "2018-07-31"]]"#;
+
+        // A leading column shifts every field, so the row is rejected.
+        let rows = parse(serde_json::from_str(row).unwrap(), &default_columns()).unwrap();
+        assert_eq!(rows[0].input.as_ref().unwrap_err().field, "strategy");
+
+        let long_row = r#"[["", "AAPL", "10", "2", "83%", "25%", "300%", "3", "2018-10-29", "2018-07-31", "call_7d_preearnings"]]"#;
+        let error = parse(serde_json::from_str(long_row).unwrap(), &default_columns()).unwrap_err();
+        assert!(error.to_string().starts_with("row 1 has 11 cells, but there are only 10 columns"), "{}", error);
+
+        let mut columns = default_columns();
+        columns.insert(0, String::new());
+        columns.pop();
+        check_columns(&columns).unwrap();
+
+        let rows = parse(serde_json::from_str(row).unwrap(), &columns).unwrap();
+        let input = rows[0].input.as_ref().unwrap();
+        assert_eq!((input.symbol.as_str(), input.wins, input.prev_earnings_result.as_str()), ("AAPL", 10, "2018-07-31"));
+
+        let columns = ["symbol", "wins", "losses", "win_rate", "avg_trade_return", "total_return", "backtest_length"];
+        let columns = columns.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(check_columns(&columns).unwrap_err().to_string(), "no `next_earnings` column");
+        assert!(check_columns(&["symbol".to_string(), "symbol".to_string()]).is_err());
+        assert!(check_columns(&["ticker".to_string()]).is_err());
+    }
+}
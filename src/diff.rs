@@ -0,0 +1,247 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use earnings::{Date, EarningsDateTime, EarningsGuess};
+use output::Trade;
+use ranking::Ranking;
+
+/// A difference between two plans.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    SymbolAdded { symbol : String, trades : Vec<String> },
+    SymbolRemoved { symbol : String },
+    TradeAdded { symbol : String, trade : String },
+    TradeRemoved { symbol : String, strategy : String },
+    /// The consensus last session before earnings moved, along with what the sources said differently.
+    EarningsMoved { symbol : String, from : Date, to : Date, reasons : Vec<String> },
+    BestStrategy { symbol : String, from : String, to : String },
+    Dates { symbol : String, strategy : String, from : (Date, Date), to : (Date, Date), earnings_moved : bool },
+}
+
+impl Display for Change {
+    fn fmt(&self, f : &mut Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Change::SymbolAdded{ ref symbol, ref trades } => write!(f, "Added {}: {}", symbol, trades.join(", ")),
+            Change::SymbolRemoved{ ref symbol } => write!(f, "Removed {}", symbol),
+            Change::TradeAdded{ ref symbol, ref trade } => write!(f, "{}: added {}", symbol, trade),
+            Change::TradeRemoved{ ref symbol, ref strategy } => write!(f, "{}: removed {}", symbol, strategy),
+            Change::EarningsMoved{ ref symbol, from, to, ref reasons } =>
+                write!(f, "{}: last session before earnings moved from {} to {} ({})", symbol, from, to, reasons.join(", ")),
+            Change::BestStrategy{ ref symbol, ref from, ref to } => write!(f, "{}: best strategy changed from {} to {}", symbol, from, to),
+            Change::Dates{ ref symbol, ref strategy, from, to, earnings_moved } => {
+                write!(f, "{} {}: {} - {} is now {} - {}", symbol, strategy, from.0, from.1, to.0, to.1)?;
+                if earnings_moved {
+                    write!(f, " because earnings moved")?;
+                } else {
+                    write!(f, " with the same earnings date")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+fn describe(trade : &Trade) -> String {
+    format!("{} {} {} - {}", trade.strategy_name, trade.test.stats(), trade.open_date, trade.close_date)
+}
+
+fn by_symbol(trades : &[Trade]) -> BTreeMap<&str, Vec<&Trade>> {
+    trades.iter()
+        .fold(BTreeMap::new(), |mut acc, trade| {
+            acc.entry(trade.symbol.as_str()).or_insert_with(Vec::new).push(trade);
+            acc
+        })
+}
+
+/// The best-performing of a symbol's trades.
+fn best<'a>(trades : &[&'a Trade], ranking : Ranking) -> &'a Trade {
+    trades.iter()
+        .max_by_key(|t| ranking.score(&t.test))
+        .cloned()
+        .expect("symbol with no trades")
+}
+
+/// Every source's estimate behind a guess.
+fn estimates(guess : &EarningsGuess) -> BTreeMap<&str, EarningsDateTime> {
+    guess.concurrences.iter()
+        .chain(guess.close_disagreements.iter())
+        .chain(guess.far_disagreements.iter())
+        .map(|e| (e.source.as_ref(), e.datetime))
+        .collect()
+}
+
+/// Explain a moved guess by the sources whose estimates changed.
+fn reasons(old : &EarningsGuess, new : &EarningsGuess) -> Vec<String> {
+    let old_estimates = estimates(old);
+    let new_estimates = estimates(new);
+
+    let mut reasons = new_estimates.iter()
+        .filter_map(|(source, new_date)| {
+            match old_estimates.get(source) {
+                Some(old_date) if old_date == new_date => None,
+                Some(old_date) => Some(format!("{} changed from {} to {}", source, old_date, new_date)),
+                None => Some(format!("{} now says {}", source, new_date)),
+            }
+        })
+        .chain(old_estimates.iter()
+            .filter(|&(source, _)| !new_estimates.contains_key(source))
+            .map(|(source, old_date)| format!("{} no longer says {}", source, old_date)))
+        .collect::<Vec<_>>();
+
+    if reasons.is_empty() {
+        reasons.push("the sources said the same things, but were weighed differently".to_string());
+    }
+
+    reasons
+}
+
+/// Compare two plans for the same symbols, picking each symbol's best trade by `ranking`.
+pub fn diff(old : &[Trade], new : &[Trade], ranking : Ranking) -> Vec<Change> {
+    let old_by_symbol = by_symbol(old);
+    let new_by_symbol = by_symbol(new);
+    let mut changes = Vec::new();
+
+    let symbols = old_by_symbol.keys().chain(new_by_symbol.keys()).cloned().collect::<BTreeSet<_>>();
+    for symbol in symbols {
+        let (old_trades, new_trades) = match (old_by_symbol.get(symbol), new_by_symbol.get(symbol)) {
+            (None, Some(new_trades)) => {
+                changes.push(Change::SymbolAdded{
+                    symbol: symbol.to_string(),
+                    trades: new_trades.iter().map(|t| describe(t)).collect(),
+                });
+                continue
+            },
+            (Some(_), None) => {
+                changes.push(Change::SymbolRemoved{ symbol: symbol.to_string() });
+                continue
+            },
+            (Some(old_trades), Some(new_trades)) => (old_trades, new_trades),
+            (None, None) => continue,
+        };
+
+        // All of a symbol's trades share the same earnings guess.
+        let old_guess = &old_trades[0].earnings;
+        let new_guess = &new_trades[0].earnings;
+        let earnings_moved = old_guess.last_session != new_guess.last_session;
+        if earnings_moved {
+            changes.push(Change::EarningsMoved{
+                symbol: symbol.to_string(),
+                from: old_guess.last_session,
+                to: new_guess.last_session,
+                reasons: reasons(old_guess, new_guess),
+            });
+        }
+
+        let (old_best, new_best) = (best(old_trades, ranking), best(new_trades, ranking));
+        if old_best.test.strategy != new_best.test.strategy {
+            changes.push(Change::BestStrategy{
+                symbol: symbol.to_string(),
+                from: format!("{} {}", old_best.strategy_name, old_best.test.stats()),
+                to: format!("{} {}", new_best.strategy_name, new_best.test.stats()),
+            });
+        }
+
+        for new_trade in new_trades.iter() {
+            match old_trades.iter().find(|t| t.test.strategy == new_trade.test.strategy) {
+                None => changes.push(Change::TradeAdded{
+                    symbol: symbol.to_string(),
+                    trade: describe(new_trade),
+                }),
+                Some(old_trade) => {
+                    if old_trade.open_date != new_trade.open_date || old_trade.close_date != new_trade.close_date {
+                        changes.push(Change::Dates{
+                            symbol: symbol.to_string(),
+                            strategy: new_trade.strategy_name.to_string(),
+                            from: (old_trade.open_date, old_trade.close_date),
+                            to: (new_trade.open_date, new_trade.close_date),
+                            earnings_moved: earnings_moved,
+                        });
+                    }
+                },
+            }
+        }
+
+        for old_trade in old_trades.iter() {
+            if new_trades.iter().find(|t| t.test.strategy == old_trade.test.strategy).is_none() {
+                changes.push(Change::TradeRemoved{
+                    symbol: symbol.to_string(),
+                    strategy: old_trade.strategy_name.to_string(),
+                });
+            }
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cmlviz::{BacktestResult, DayCount};
+    use earnings::{AnnounceTime, SourcedEarningsTime};
+
+    fn trade(symbol : &str, strategy : &str, avg_trade_return : i32, last_session : u32, estimates : Vec<SourcedEarningsTime>) -> Trade {
+        let test = BacktestResult::sample(strategy)
+            .with_symbol(symbol)
+            .with_returns(avg_trade_return, 300)
+            .with_earnings(Date::from_ymd(2018, 10, last_session));
+        let guess = EarningsGuess::sample(Date::from_ymd(2018, 10, last_session)).with_concurrences(estimates);
+        let strategy = test.strategy;
+        let open_date = strategy.open_date(guess.last_session, DayCount::Trading);
+        let close_date = strategy.close_date(guess.last_session, DayCount::Trading);
+        Trade::new(open_date, close_date, &[test], 0, &guess, vec![], Ranking::Weighted)
+    }
+
+    #[test]
+    fn no_changes() {
+        let plan = vec![trade("AAPL", "call_7d_preearnings", 25, 29, vec![SourcedEarningsTime::sample("FinViz", 2018, 10, 29, AnnounceTime::AfterMarket)])];
+        assert!(diff(&plan, &plan, Ranking::Weighted).is_empty());
+    }
+
+    #[test]
+    fn added_and_removed_symbols() {
+        let old = vec![trade("AAPL", "call_7d_preearnings", 25, 29, vec![])];
+        let new = vec![trade("MSFT", "call_7d_preearnings", 25, 24, vec![])];
+        let changes = diff(&old, &new, Ranking::Weighted).iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(changes, vec![
+            "Removed AAPL",
+            "Added MSFT: E-7 Call (25%,10/2) 2018-10-15 - 2018-10-24",
+        ]);
+    }
+
+    #[test]
+    fn earnings_moved() {
+        let old = vec![trade("AAPL", "call_7d_preearnings", 25, 29, vec![
+            SourcedEarningsTime::sample("FinViz", 2018, 10, 29, AnnounceTime::AfterMarket),
+            SourcedEarningsTime::sample("Zacks", 2018, 10, 29, AnnounceTime::AfterMarket),
+        ])];
+        let new = vec![trade("AAPL", "call_7d_preearnings", 25, 30, vec![
+            SourcedEarningsTime::sample("FinViz", 2018, 10, 30, AnnounceTime::AfterMarket),
+            SourcedEarningsTime::sample("Yahoo", 2018, 10, 30, AnnounceTime::Unknown),
+        ])];
+
+        let changes = diff(&old, &new, Ranking::Weighted).iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(changes, vec![
+            "AAPL: last session before earnings moved from 2018-10-29 to 2018-10-30 (FinViz changed from 2018-10-29 AMC to \
+             2018-10-30 AMC, Yahoo now says 2018-10-30, Zacks no longer says 2018-10-29 AMC)",
+            "AAPL E-7 Call: 2018-10-18 - 2018-10-29 is now 2018-10-19 - 2018-10-30 because earnings moved",
+        ]);
+    }
+
+    #[test]
+    fn best_strategy_changed() {
+        let old = vec![
+            trade("AAPL", "call_7d_preearnings", 25, 29, vec![]),
+            trade("AAPL", "call_14d_preearnings", 20, 29, vec![]),
+        ];
+        let new = vec![
+            trade("AAPL", "call_7d_preearnings", 25, 29, vec![]),
+            trade("AAPL", "call_14d_preearnings", 30, 29, vec![]),
+        ];
+
+        let changes = diff(&old, &new, Ranking::Weighted).iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(changes, vec![
+            "AAPL: best strategy changed from E-7 Call (25%,10/2) to E-14 Call (30%,10/2)",
+        ]);
+    }
+}
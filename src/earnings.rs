@@ -5,14 +5,23 @@ use crossbeam;
 use itertools::Itertools;
 use slog;
 use reqwest;
+use reqwest::header::{Headers, UserAgent};
+use std::time::Duration as StdDuration;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::collections::HashMap;
+use std::str::FromStr;
 use failure::{Error, ResultExt, err_msg};
 use scraper::{Html, Selector};
 use chrono;
-use chrono::{NaiveDate, Datelike, Timelike, Weekday, Duration, DateTime, TimeZone, Utc};
+use chrono::{NaiveDate, NaiveTime, Datelike, Timelike, Duration, TimeZone, Utc};
 use regex::Regex;
 use json;
 use htmlescape::decode_html;
+use calendar;
+use cmlviz;
+use clock::Clock;
 
 #[derive(Debug, Fail)]
 enum EarningsError {
@@ -23,51 +32,239 @@ enum EarningsError {
     JsonPayloadNotFound,
 }
 
-struct EarningsSource {
+type ExtractFn = fn(logger : &slog::Logger, clock : &Clock, body : &str) -> Result<Option<EarningsDateTime>,Error>;
+
+/// A website that publishes earnings dates.
+pub trait EarningsSource : Send + Sync {
+    fn name(&self) -> &str;
+
+    /// The URL of the page to scrape, with `{}` in place of the symbol.
+    fn default_url(&self) -> &str;
+
+    /// Whether to use this source when the settings don't say otherwise.
+    fn enabled_by_default(&self) -> bool { true }
+
+    /// Find the earnings date in the body of the page. Dates that depend on the current date, such as the
+    /// next of several releases, should use `clock` rather than the system time.
+    fn extract(&self, logger : &slog::Logger, clock : &Clock, body : &str) -> Result<Option<EarningsDateTime>, Error>;
+}
+
+/// A source backed by one of the extract functions in this module.
+struct ScrapedSource {
     name : &'static str,
-    url: &'static str,
-    extract: (fn(logger : &slog::Logger, reqwest::Response) -> Result<Option<EarningsDateTime>,Error>),
+    url : &'static str,
+    enabled : bool,
+    extract : ExtractFn,
 }
 
-static SOURCES : &[EarningsSource] = &[
-        // EarningsSource{
-        //     name: "Bloomberg",
-        //     url: "https://www.bloomberg.com/quote/{}:US",
-        //     extract: extract_bloomberg,
-        // },
+impl EarningsSource for ScrapedSource {
+    fn name(&self) -> &str { self.name }
+    fn default_url(&self) -> &str { self.url }
+    fn enabled_by_default(&self) -> bool { self.enabled }
+
+    fn extract(&self, logger : &slog::Logger, clock : &Clock, body : &str) -> Result<Option<EarningsDateTime>, Error> {
+        (self.extract)(logger, clock, body)
+    }
+}
+
+fn builtin_sources() -> Vec<Box<EarningsSource>> {
+    vec![
+        Box::new(ScrapedSource{
+            name: "Bloomberg",
+            url: "https://www.bloomberg.com/quote/{}:US",
+            enabled: false,
+            extract: extract_bloomberg,
+        }),
         // NASDAQ seeems to have aggressive anti-scraping measures in place, or something.
         // The data is taken from Zack's anyway, so not a big deal.
-        // EarningsSource{
-        //     name: "NASDAQ",
-        //     url: "http://www.nasdaq.com/earnings/report/{}",
-        //     extract: extract_nasdaq,
-        // },
-        EarningsSource{
+        Box::new(ScrapedSource{
+            name: "NASDAQ",
+            url: "http://www.nasdaq.com/earnings/report/{}",
+            enabled: false,
+            extract: extract_nasdaq,
+        }),
+        Box::new(ScrapedSource{
             name: "FinViz",
             url: "https://finviz.com/quote.ashx?t={}",
+            enabled: true,
             extract: extract_finviz,
-        },
-        EarningsSource{
+        }),
+        Box::new(ScrapedSource{
             name: "Yahoo",
             url: "https://finance.yahoo.com/quote/{}",
+            enabled: true,
             extract: extract_yahoo,
-        },
-        EarningsSource{
+        }),
+        Box::new(ScrapedSource{
             name: "Zacks",
             url: "https://www.zacks.com/stock/quote/{}",
+            enabled: true,
             extract: extract_zacks,
-        },
-        EarningsSource{
+        }),
+        Box::new(ScrapedSource{
             name: "Estimize",
             url: "https://www.estimize.com/{}",
+            enabled: true,
             extract: extract_estimize,
+        }),
+    ]
+}
+
+static USER_AGENT : &'static str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_13_2) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/63.0.3239.132 Safari/537.36";
+
+/// Per-source settings from the `sources` section of the settings file, keyed by source name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SourceSettings {
+    pub enabled : Option<bool>,
+    /// Replaces the source's URL. `{}` is replaced with the symbol.
+    pub url : Option<String>,
+    /// Extra request headers.
+    pub headers : HashMap<String, String>,
+    /// Request timeout in seconds.
+    pub timeout : Option<u64>,
+    /// How much this source counts toward the earnings date consensus.
+    pub weight : f64,
+}
+
+impl Default for SourceSettings {
+    fn default() -> SourceSettings {
+        SourceSettings{
+            enabled: None,
+            url: None,
+            headers: HashMap::new(),
+            timeout: None,
+            weight: 1.0,
+        }
+    }
+}
+
+struct RegisteredSource {
+    source : Box<EarningsSource>,
+    settings : SourceSettings,
+    client : reqwest::Client,
+}
+
+impl RegisteredSource {
+    fn enabled(&self) -> bool {
+        self.settings.enabled.unwrap_or_else(|| self.source.enabled_by_default())
+    }
+
+    fn url(&self, symbol : &str) -> String {
+        self.settings.url.as_ref().map_or(self.source.default_url(), |u| u.as_str()).replace("{}", symbol)
+    }
+}
+
+/// A page that an earnings date is scraped from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourcePage {
+    pub source : String,
+    pub url : String,
+}
+
+/// The earnings sources available to `get_earnings_date_estimates`, with their settings applied.
+pub struct SourceRegistry {
+    sources : Vec<RegisteredSource>,
+    settings : HashMap<String, SourceSettings>,
+}
+
+impl SourceRegistry {
+    /// Create a registry containing the built-in sources.
+    pub fn new(logger : &slog::Logger, settings : HashMap<String, SourceSettings>) -> Result<SourceRegistry, Error> {
+        let mut registry = SourceRegistry{
+            sources: Vec::new(),
+            settings: settings,
+        };
+
+        for source in builtin_sources() {
+            registry.register(logger, source)?;
+        }
+
+        for name in registry.settings.keys() {
+            // The CML date isn't fetched, but it can still be given a weight.
+            if name.eq_ignore_ascii_case(cmlviz::SOURCE_NAME) {
+                continue
+            }
+
+            if registry.sources.iter().find(|s| s.source.name().eq_ignore_ascii_case(name)).is_none() {
+                warn!(logger, "Settings for unknown earnings source {}", name);
+            }
         }
-    ];
+
+        Ok(registry)
+    }
+
+    /// Add a source, applying any settings with a matching name.
+    pub fn register(&mut self, logger : &slog::Logger, source : Box<EarningsSource>) -> Result<(), Error> {
+        let settings = self.settings.iter()
+            .find(|&(name, _)| source.name().eq_ignore_ascii_case(name))
+            .map_or_else(SourceSettings::default, |(_, s)| s.clone());
+
+        let mut headers = Headers::new();
+        headers.set(UserAgent::new(USER_AGENT));
+        for (name, value) in settings.headers.iter() {
+            headers.set_raw(name.clone(), value.clone());
+        }
+
+        let mut builder = reqwest::Client::builder();
+        builder.default_headers(headers);
+        if let Some(timeout) = settings.timeout {
+            builder.timeout(StdDuration::from_secs(timeout));
+        }
+        let client = builder.build().with_context(|_| format!("building client for {}", source.name()))?;
+
+        let registered = RegisteredSource{
+            source: source,
+            settings: settings,
+            client: client,
+        };
+
+        if registered.enabled() {
+            debug!(logger, "Using earnings source {} with weight {}", registered.source.name(), registered.settings.weight);
+        }
+
+        self.sources.push(registered);
+        Ok(())
+    }
+
+    /// The name and URL of each page that gave one of the guess's estimates, whether it was fetched on this run or when the
+    /// guess was cached.
+    pub fn pages(&self, symbol : &str, guess : &EarningsGuess) -> Vec<SourcePage> {
+        let estimates = guess.concurrences.iter()
+            .chain(guess.close_disagreements.iter())
+            .chain(guess.far_disagreements.iter())
+            .collect::<Vec<_>>();
+
+        self.sources.iter()
+            .filter(|source| estimates.iter().any(|e| e.source == source.source.name()))
+            .map(|source| SourcePage{
+                source: source.source.name().to_string(),
+                url: source.url(symbol),
+            })
+            .collect()
+    }
+
+    /// Multiply the named source's weight, such as by how often it's been right.
+    pub fn scale_weight(&mut self, name : &str, factor : f64) {
+        let key = self.settings.keys()
+            .find(|n| n.eq_ignore_ascii_case(name))
+            .cloned()
+            .unwrap_or_else(|| name.to_string());
+        self.settings.entry(key).or_insert_with(SourceSettings::default).weight *= factor;
+    }
+
+    /// How much the named source counts toward the consensus in `best_earnings_guess`.
+    pub fn weight(&self, name : &str) -> f64 {
+        self.settings.iter()
+            .find(|&(n, _)| n.eq_ignore_ascii_case(name))
+            .map_or(1.0, |(_, s)| s.weight)
+    }
+}
 
 
 pub type Date = NaiveDate;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AnnounceTime {
     BeforeMarket,
     AfterMarket,
@@ -84,42 +281,75 @@ impl Display for AnnounceTime {
     }
 }
 
-/// If the date falls on a weekend, step back to the closest weekday.
+impl FromStr for AnnounceTime {
+    type Err = Error;
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bmo" => Ok(AnnounceTime::BeforeMarket),
+            "amc" => Ok(AnnounceTime::AfterMarket),
+            "unknown" => Ok(AnnounceTime::Unknown),
+            _ => Err(err_msg(format!("Unknown announcement time {}, expected amc or bmo", s))),
+        }
+    }
+}
+
+/// Trading-day arithmetic, skipping weekends and exchange holidays.
 pub trait DatelikeExt {
-    /// Get the closest trading day to this one, always going backwards on a weekend.
+    fn is_trading_day(&self) -> bool;
+    /// Get the closest trading day to this one, always going backwards on a weekend or holiday.
     fn closest_trading_day(&self) -> Self;
     fn next_trading_day(&self) -> Self;
     fn prev_trading_day(&self) -> Self;
-
+    /// Move this many sessions forward, or backward if `sessions` is negative.
+    /// An offset of 0 is the same as `closest_trading_day`.
+    fn add_trading_days(&self, sessions : i64) -> Self;
+    /// The closing time if this is a shortened session.
+    fn early_close(&self) -> Option<NaiveTime>;
 }
 
 impl DatelikeExt for Date {
+    fn is_trading_day(&self) -> bool {
+        calendar::is_trading_day(*self)
+    }
+
     fn closest_trading_day(&self) -> Date {
-        match self.weekday() {
-            Weekday::Sat => *self - Duration::days(1),
-            Weekday::Sun => *self - Duration::days(2),
-            _ => *self,
+        let mut date = *self;
+        while !date.is_trading_day() {
+            date = date.pred();
         }
+        date
     }
 
     fn next_trading_day(&self) -> Date {
-        match self.weekday() {
-            Weekday::Fri => *self + Duration::days(3),
-            Weekday::Sat => *self + Duration::days(2),
-            _ => self.succ(),
+        let mut date = self.succ();
+        while !date.is_trading_day() {
+            date = date.succ();
         }
+        date
     }
 
     fn prev_trading_day(&self) -> Date {
-        match self.weekday() {
-            Weekday::Mon => *self - Duration::days(3),
-            Weekday::Sun => *self - Duration::days(2),
-            _ => self.pred(),
+        self.pred().closest_trading_day()
+    }
+
+    fn add_trading_days(&self, sessions : i64) -> Date {
+        if sessions == 0 {
+            return self.closest_trading_day()
+        }
+
+        let mut date = *self;
+        for _ in 0..sessions.abs() {
+            date = if sessions > 0 { date.next_trading_day() } else { date.prev_trading_day() };
         }
+        date
+    }
+
+    fn early_close(&self) -> Option<NaiveTime> {
+        calendar::early_close(*self)
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct EarningsDateTime{
     pub date: Date,
     pub time: AnnounceTime,
@@ -157,9 +387,30 @@ pub struct EarningsGuess {
     pub concurrences : Vec<SourcedEarningsTime>,
     pub close_disagreements : Vec<SourcedEarningsTime>,
     pub far_disagreements : Vec<SourcedEarningsTime>,
+    /// From 0 to 1, how strongly the sources agree on `last_session`. Guesses cached before this was added have 0.
+    #[serde(default)]
+    pub confidence : f64,
+}
+
+impl EarningsGuess {
+    /// The announcement date and time the concurring sources give. The time is unknown unless one of them has it.
+    pub fn announcement(&self) -> EarningsDateTime {
+        self.concurrences.iter()
+            .map(|c| c.datetime)
+            .find(|d| d.time != AnnounceTime::Unknown && d.last_session().0 == self.last_session)
+            .unwrap_or(EarningsDateTime{
+                date: self.last_session,
+                time: AnnounceTime::Unknown,
+            })
+    }
 }
 
-pub fn best_earnings_guess(dates : &[SourcedEarningsTime]) -> Option<EarningsGuess> {
+/// How much a source's vote counts when it gives the announcement time, relative to its weight.
+const EXACT_SCORE : f64 = 1.0;
+/// How much a source's vote counts for each of the sessions it could mean when the announcement time is unknown.
+const FUZZY_SCORE : f64 = 0.5;
+
+pub fn best_earnings_guess(clock : &Clock, sources : &SourceRegistry, dates : &[SourcedEarningsTime]) -> Option<EarningsGuess> {
 
     let mut guesses : HashMap<Date, Vec<(&SourcedEarningsTime, bool)>> = HashMap::new();
 
@@ -182,14 +433,12 @@ pub fn best_earnings_guess(dates : &[SourcedEarningsTime]) -> Option<EarningsGue
         }
     }
 
-    // Now that they're grouped by date, figure out which one is the best guess.
-    let mut highest_fuzzy_count = 0;
-    let mut highest_fuzzy_date = Date::from_num_days_from_ce(1);
-    let mut highest_exact_count = 0;
-    let mut highest_exact_date = Date::from_num_days_from_ce(1);
+    // Now that they're grouped by date, score each one by the weight of the sources that picked it, giving preference
+    // to the earliest date.
+    let mut best_score = 0.0;
+    let mut best_date = Date::from_num_days_from_ce(1);
 
-    // Get the highest count for both exact dates and fuzzy dates, giving preference to the earliest date.
-    let today = chrono::Local::today().naive_local();
+    let today = clock.today();
     for (date, guess) in guesses.iter() {
         let date = *date;
         if date < today {
@@ -197,25 +446,24 @@ pub fn best_earnings_guess(dates : &[SourcedEarningsTime]) -> Option<EarningsGue
             continue
         }
 
-        let fuzzy_count = guess.len();
-        let exact_count = guess.iter().filter(|&&(_, from_fuzzy)| from_fuzzy).count();
-
-        if fuzzy_count > highest_fuzzy_count || (fuzzy_count == highest_fuzzy_count && date < highest_fuzzy_date) {
-            highest_fuzzy_count = fuzzy_count;
-            highest_fuzzy_date = date;
-        }
+        let score = guess.iter()
+            .map(|&(g, from_fuzzy)| sources.weight(&g.source) * if from_fuzzy { FUZZY_SCORE } else { EXACT_SCORE })
+            .sum::<f64>();
 
-        if exact_count > highest_exact_count || (exact_count == highest_exact_count && date < highest_exact_date) {
-            highest_exact_count = fuzzy_count;
-            highest_exact_date = date;
+        if score > best_score || (score == best_score && date < best_date) {
+            best_score = score;
+            best_date = date;
         }
     }
 
-    if highest_fuzzy_count == 0 {
+    if best_score <= 0.0 {
         return None
     }
 
-    let best_date = highest_fuzzy_date;
+    // The confidence is the share of the best possible score, which is every source agreeing on the date and time.
+    let possible_score = dates.iter().map(|d| sources.weight(&d.source) * EXACT_SCORE).sum::<f64>();
+    let confidence = best_score / possible_score;
+
     let concurrences = guesses.remove(&best_date).unwrap_or_else(Vec::new).iter().map(|&(guess, _)| guess.clone()).collect::<Vec<_>>();
 
     let prev_date = guesses.remove(&best_date.prev_trading_day()).unwrap_or_else(Vec::new);
@@ -248,12 +496,12 @@ pub fn best_earnings_guess(dates : &[SourcedEarningsTime]) -> Option<EarningsGue
         concurrences: concurrences,
         close_disagreements: close_disagreements,
         far_disagreements: far_disagreements,
+        confidence: confidence,
     })
 }
 
-#[allow(dead_code)]
-fn extract_bloomberg(_logger : &slog::Logger, mut response : reqwest::Response) -> Result<Option<EarningsDateTime>, Error> {
-    let document = Html::parse_document(response.text()?.as_str());
+fn extract_bloomberg(_logger : &slog::Logger, _clock : &Clock, text : &str) -> Result<Option<EarningsDateTime>, Error> {
+    let document = Html::parse_document(text);
     let selector = Selector::parse(r#"span[class^="nextAnnouncementDate"]"#).unwrap();
     document.select(&selector)
         .next()
@@ -264,14 +512,13 @@ fn extract_bloomberg(_logger : &slog::Logger, mut response : reqwest::Response)
 }
 
 
-#[allow(dead_code)]
-fn extract_nasdaq(_logger : &slog::Logger, mut response : reqwest::Response) -> Result<Option<EarningsDateTime>, Error> {
+fn extract_nasdaq(_logger : &slog::Logger, _clock : &Clock, text : &str) -> Result<Option<EarningsDateTime>, Error> {
 
     lazy_static! {
         static ref RE: Regex = Regex::new(r#"earnings on\s*(\d{1,2}/\d{1,2}/\d{4})\s*(after market close|before market open)?."#).unwrap();
     }
 
-    let document = Html::parse_document(response.text()?.as_str());
+    let document = Html::parse_document(text);
     let selector = Selector::parse(r#"#two_column_main_content_reportdata"#).unwrap();
     document.select(&selector)
         .next()
@@ -304,13 +551,22 @@ fn extract_nasdaq(_logger : &slog::Logger, mut response : reqwest::Response) ->
 
 }
 
-fn extract_finviz(_logger : &slog::Logger, mut response : reqwest::Response) -> Result<Option<EarningsDateTime>, Error> {
+/// Figure out the year for a FinViz date, which doesn't include one.
+fn finviz_date(month : u32, day : u32, today : Date) -> Date {
+    let mut date = Date::from_ymd(today.year(), month, day);
+    // If it's in the past (minus a bit of buffer for recent earnings), then it's probably next year.
+    if date < (today - Duration::days(30)) {
+        date = date.with_year(date.year() + 1).unwrap();
+    }
+    date
+}
+
+fn extract_finviz(_logger : &slog::Logger, clock : &Clock, text : &str) -> Result<Option<EarningsDateTime>, Error> {
     lazy_static! {
         static ref RE: Regex = Regex::new(r#"(\S+ \d{1,2})\s*(AMC|BMO)?"#).unwrap();
     }
 
-    let text = response.text()?;
-    let document = Html::parse_document(text.as_str());
+    let document = Html::parse_document(text);
     let selector = Selector::parse(r#"table.snapshot-table2 tr:nth-child(11) > td:nth-child(6) > b"#).unwrap();
 
     document.select(&selector)
@@ -332,12 +588,7 @@ fn extract_finviz(_logger : &slog::Logger, mut response : reqwest::Response) ->
                     cap.get(1).map(|text| {
                         let mut parsed = chrono::format::Parsed::new();
                         chrono::format::parse(&mut parsed, text.as_str(), chrono::format::strftime::StrftimeItems::new("%b %d"))?;
-                        let today = chrono::Local::today().naive_local();
-                        let mut date = Date::from_ymd(today.year(), parsed.month.unwrap(), parsed.day.unwrap());
-                        // If it's in the past (minus a bit of buffer for recent earnings), then it's probably next year.
-                        if date < (today - Duration::days(30)) {
-                            date = date.with_year(date.year() + 1).unwrap();
-                        }
+                        let date = finviz_date(parsed.month.unwrap(), parsed.day.unwrap(), clock.today());
 
                         Ok(EarningsDateTime {
                             date: date,
@@ -350,12 +601,10 @@ fn extract_finviz(_logger : &slog::Logger, mut response : reqwest::Response) ->
         .map_or(Ok(None), |v| v.map(Some)) // Switch Option<Result<T, E>> to Result<Option<T>, Error>
 }
 
-fn extract_yahoo(_logger : &slog::Logger, mut response : reqwest::Response) -> Result<Option<EarningsDateTime>, Error> {
-    let text = response.text()?;
+fn extract_yahoo(_logger : &slog::Logger, _clock : &Clock, text : &str) -> Result<Option<EarningsDateTime>, Error> {
     let prefix = "root.App.main = ";
 
-    text.as_str()
-        .lines()
+    text.lines()
         .find(|line| line.starts_with(prefix))
         .ok_or_else(|| Error::from(EarningsError::JsonPayloadNotFound))
         .and_then(|line| {
@@ -373,9 +622,8 @@ fn extract_yahoo(_logger : &slog::Logger, mut response : reqwest::Response) -> R
         })
 }
 
-fn extract_zacks(_logger : &slog::Logger, mut response : reqwest::Response) -> Result<Option<EarningsDateTime>, Error> {
-    let text = response.text()?;
-    let document = Html::parse_document(text.as_str());
+fn extract_zacks(_logger : &slog::Logger, _clock : &Clock, text : &str) -> Result<Option<EarningsDateTime>, Error> {
+    let document = Html::parse_document(text);
     let main_selector = Selector::parse(r#"#stock_key_earnings > table > tbody > tr:nth-child(5) > td:nth-child(2)"#).unwrap();
     let sup_selector = Selector::parse(r#"sup"#).unwrap();
 
@@ -407,17 +655,15 @@ fn extract_zacks(_logger : &slog::Logger, mut response : reqwest::Response) -> R
         .map_or(Ok(None), |v| v.map(Some)) // Switch Option<Result<T, E>> to Result<Option<T>, Error>
 }
 
-fn extract_estimize(_logger : &slog::Logger, mut response : reqwest::Response) -> Result<Option<EarningsDateTime>, Error> {
+fn extract_estimize(_logger : &slog::Logger, clock : &Clock, text : &str) -> Result<Option<EarningsDateTime>, Error> {
     lazy_static! {
         static ref MATCH_RE: Regex = Regex::new(r#"(data-react-class|component_path)="releases/app""#).unwrap();
         static ref EXTRACT_RE: Regex = Regex::new(r#"data(-react-props)?="(.*)" component_path"#).unwrap();
-        static ref TODAY : DateTime<Utc> = Utc::now();
     }
 
-    let text = response.text()?;
+    let now = clock.now();
 
-    text.as_str()
-        .lines()
+    text.lines()
         .find(|line| MATCH_RE.is_match(line))
         .ok_or_else(|| err_msg("match re did not match"))
         .and_then(|line| {
@@ -439,7 +685,7 @@ fn extract_estimize(_logger : &slog::Logger, mut response : reqwest::Response) -
                     let report_time = val["reportsAt"].as_i64().unwrap_or(0) / 1000;
                     Utc.timestamp(report_time, 0)
                 })
-                .find(|date| date > &*TODAY /* This is something weird with lazy_static? Probably a better way to do this */)
+                .find(|date| date > &now)
                 .map(|date| {
                     // Market closes at 4:00 PM EST. Accounting for DST we'll check for 8:00 PM UTC as the market close time.
                     let after_market = date.hour() > 20;
@@ -456,22 +702,68 @@ fn extract_estimize(_logger : &slog::Logger, mut response : reqwest::Response) -
         })
 }
 
-pub fn get_earnings_date_estimates(logger : &slog::Logger, client : &reqwest::Client, symbol : &str) -> Vec<SourcedEarningsTime> {
+/// Where `get_earnings_date_estimates` gets the source pages from.
+#[derive(Debug, Clone)]
+pub enum FetchMode {
+    Live,
+    /// Fetch from the network and save each page under this directory.
+    Record(PathBuf),
+    /// Read the pages saved by an earlier `Record` run instead of using the network.
+    Replay(PathBuf),
+}
+
+impl FetchMode {
+    fn page_path(dir : &Path, source : &str, symbol : &str) -> PathBuf {
+        dir.join(source).join(format!("{}.html", symbol))
+    }
+
+    fn fetch(&self, source : &RegisteredSource, symbol : &str) -> Result<String, Error> {
+        let url = source.url(symbol);
+        match *self {
+            FetchMode::Replay(ref dir) => {
+                let path = FetchMode::page_path(dir, source.source.name(), symbol);
+                let mut body = String::new();
+                File::open(&path)
+                    .and_then(|mut f| f.read_to_string(&mut body))
+                    .with_context(|_| format!("replaying {} from {}", url, path.display()))?;
+                Ok(body)
+            },
+            FetchMode::Live | FetchMode::Record(_) => {
+                let mut response = source.client.get(url.as_str()).send().with_context(|_| format!("URL {}", url))?;
+                let is_success = response.status().is_success();
+                if !is_success {
+                    return Err(response.error_for_status().unwrap_err().into());
+                }
+
+                let body = response.text().with_context(|_| format!("URL {}", url))?;
+
+                if let FetchMode::Record(ref dir) = *self {
+                    let path = FetchMode::page_path(dir, source.source.name(), symbol);
+                    path.parent().map_or(Ok(()), |p| fs::create_dir_all(p))
+                        .and_then(|_| File::create(&path))
+                        .and_then(|mut f| f.write_all(body.as_bytes()))
+                        .with_context(|_| format!("recording {} to {}", url, path.display()))?;
+                }
+
+                Ok(body)
+            },
+        }
+    }
+}
+
+pub fn get_earnings_date_estimates(logger : &slog::Logger, clock : &Clock, sources : &SourceRegistry, fetch : &FetchMode, symbol : &str) -> Vec<SourcedEarningsTime> {
     crossbeam::scope(|scope| {
-        let joins = SOURCES.iter()
+        let joins = sources.sources.iter()
+            .filter(|source| source.enabled())
             .map(|source| {
                 scope.spawn(move || {
-                    let url = source.url.replace("{}", symbol);
-                    let response = client.get(url.as_str()).send().with_context(|_| format!("URL {}", url))?;
-                    let is_success = response.status().is_success();
-                    if !is_success {
-                        return Err(response.error_for_status().unwrap_err().into());
-                    }
+                    let url = source.url(symbol);
+                    let body = fetch.fetch(source, symbol)?;
 
-                    let d = (source.extract)(logger, response).with_context(|_| format!("URL {}", url))?
+                    let d = source.source.extract(logger, clock, body.as_str()).with_context(|_| format!("URL {}", url))?
                         .map(|datetime| SourcedEarningsTime{
                             datetime: datetime,
-                            source: source.name.into(),
+                            source: source.source.name().to_string().into(),
                         });
 
                     if d.is_none() {
@@ -501,3 +793,309 @@ pub fn get_earnings_date_estimates(logger : &slog::Logger, client : &reqwest::Cl
             .collect::<Vec<_>>()
     })
 }
+
+#[cfg(test)]
+impl EarningsDateTime {
+    pub fn sample(year : i32, month : u32, day : u32, time : AnnounceTime) -> EarningsDateTime {
+        EarningsDateTime{
+            date: Date::from_ymd(year, month, day),
+            time: time,
+        }
+    }
+}
+
+#[cfg(test)]
+impl SourcedEarningsTime {
+    pub fn sample(source : &'static str, year : i32, month : u32, day : u32, time : AnnounceTime) -> SourcedEarningsTime {
+        SourcedEarningsTime{
+            source: source.into(),
+            datetime: EarningsDateTime::sample(year, month, day, time),
+        }
+    }
+}
+
+#[cfg(test)]
+impl EarningsGuess {
+    /// A guess for `last_session` with full confidence and no sources.
+    pub fn sample(last_session : Date) -> EarningsGuess {
+        EarningsGuess{
+            last_session: last_session,
+            concurrences: vec![],
+            close_disagreements: vec![],
+            far_disagreements: vec![],
+            confidence: 1.0,
+        }
+    }
+
+    pub fn with_concurrences(mut self, concurrences : Vec<SourcedEarningsTime>) -> EarningsGuess {
+        self.concurrences = concurrences;
+        self
+    }
+
+    pub fn with_far_disagreements(mut self, far_disagreements : Vec<SourcedEarningsTime>) -> EarningsGuess {
+        self.far_disagreements = far_disagreements;
+        self
+    }
+
+    pub fn with_confidence(mut self, confidence : f64) -> EarningsGuess {
+        self.confidence = confidence;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::logger;
+
+    /// The fixtures were recorded ahead of the October 2018 earnings season.
+    fn as_of() -> Clock {
+        Clock::AsOf(Date::from_ymd(2018, 10, 1))
+    }
+
+    /// Run a page from tests/fixtures, which has the same layout as a `--record` directory, through an extractor.
+    fn extract(extractor : ExtractFn, source : &str, symbol : &str) -> Result<Option<EarningsDateTime>, Error> {
+        extract_as_of(extractor, &as_of(), source, symbol)
+    }
+
+    fn extract_as_of(extractor : ExtractFn, clock : &Clock, source : &str, symbol : &str) -> Result<Option<EarningsDateTime>, Error> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(source).join(format!("{}.html", symbol));
+        let mut body = String::new();
+        File::open(&path)
+            .and_then(|mut f| f.read_to_string(&mut body))
+            .unwrap_or_else(|e| panic!("reading {}: {}", path.display(), e));
+        extractor(&logger(), clock, body.as_str())
+    }
+
+    #[test]
+    fn finviz_after_market() {
+        assert_eq!(extract(extract_finviz, "FinViz", "AAPL").unwrap(), Some(EarningsDateTime::sample(2018, 10, 29, AnnounceTime::AfterMarket)));
+    }
+
+    #[test]
+    fn finviz_before_market() {
+        // January has already passed, so this is next year's.
+        assert_eq!(extract(extract_finviz, "FinViz", "MSFT").unwrap(), Some(EarningsDateTime::sample(2019, 1, 24, AnnounceTime::BeforeMarket)));
+    }
+
+    #[test]
+    fn finviz_unknown_time() {
+        assert_eq!(extract(extract_finviz, "FinViz", "IBM").unwrap(), Some(EarningsDateTime::sample(2018, 10, 16, AnnounceTime::Unknown)));
+    }
+
+    #[test]
+    fn finviz_missing_date() {
+        assert_eq!(extract(extract_finviz, "FinViz", "NONE").unwrap(), None);
+    }
+
+    #[test]
+    fn finviz_as_of_new_year() {
+        let clock = Clock::AsOf(Date::from_ymd(2019, 1, 2));
+        assert_eq!(extract_as_of(extract_finviz, &clock, "FinViz", "MSFT").unwrap(), Some(EarningsDateTime::sample(2019, 1, 24, AnnounceTime::BeforeMarket)));
+        assert_eq!(extract_as_of(extract_finviz, &clock, "FinViz", "AAPL").unwrap(), Some(EarningsDateTime::sample(2019, 10, 29, AnnounceTime::AfterMarket)));
+    }
+
+    #[test]
+    fn finviz_year_rollover() {
+        // Early next year
+        assert_eq!(finviz_date(1, 24, Date::from_ymd(2018, 12, 20)), Date::from_ymd(2019, 1, 24));
+        // Later this year
+        assert_eq!(finviz_date(12, 20, Date::from_ymd(2018, 1, 10)), Date::from_ymd(2018, 12, 20));
+        // Recently reported
+        assert_eq!(finviz_date(1, 24, Date::from_ymd(2018, 2, 10)), Date::from_ymd(2018, 1, 24));
+        // Reported long enough ago that the next one is meant
+        assert_eq!(finviz_date(1, 24, Date::from_ymd(2018, 3, 10)), Date::from_ymd(2019, 1, 24));
+    }
+
+    #[test]
+    fn yahoo() {
+        assert_eq!(extract(extract_yahoo, "Yahoo", "AAPL").unwrap(), Some(EarningsDateTime::sample(2018, 10, 29, AnnounceTime::Unknown)));
+    }
+
+    #[test]
+    fn yahoo_missing_payload() {
+        assert!(extract(extract_yahoo, "Yahoo", "NONE").is_err());
+    }
+
+    #[test]
+    fn zacks_after_market() {
+        assert_eq!(extract(extract_zacks, "Zacks", "AAPL").unwrap(), Some(EarningsDateTime::sample(2018, 10, 29, AnnounceTime::AfterMarket)));
+    }
+
+    #[test]
+    fn zacks_before_market() {
+        assert_eq!(extract(extract_zacks, "Zacks", "MSFT").unwrap(), Some(EarningsDateTime::sample(2019, 1, 24, AnnounceTime::BeforeMarket)));
+    }
+
+    #[test]
+    fn zacks_unknown_time() {
+        assert_eq!(extract(extract_zacks, "Zacks", "IBM").unwrap(), Some(EarningsDateTime::sample(2018, 10, 16, AnnounceTime::Unknown)));
+    }
+
+    #[test]
+    fn zacks_missing_table() {
+        assert!(extract(extract_zacks, "Zacks", "NONE").is_err());
+    }
+
+    #[test]
+    fn estimize_next_release_after_market() {
+        // The FQ3 release has already happened, so the first one after the as-of date is used.
+        assert_eq!(extract(extract_estimize, "Estimize", "AAPL").unwrap(), Some(EarningsDateTime::sample(2018, 10, 29, AnnounceTime::AfterMarket)));
+    }
+
+    #[test]
+    fn estimize_next_release_before_market() {
+        assert_eq!(extract(extract_estimize, "Estimize", "MSFT").unwrap(), Some(EarningsDateTime::sample(2019, 1, 24, AnnounceTime::BeforeMarket)));
+    }
+
+    #[test]
+    fn estimize_as_of_earlier_release() {
+        let clock = Clock::AsOf(Date::from_ymd(2018, 7, 1));
+        assert_eq!(extract_as_of(extract_estimize, &clock, "Estimize", "AAPL").unwrap(), Some(EarningsDateTime::sample(2018, 7, 31, AnnounceTime::AfterMarket)));
+    }
+
+    #[test]
+    fn estimize_missing_releases() {
+        assert!(extract(extract_estimize, "Estimize", "NONE").is_err());
+    }
+
+    #[test]
+    fn bloomberg() {
+        assert_eq!(extract(extract_bloomberg, "Bloomberg", "AAPL").unwrap(), Some(EarningsDateTime::sample(2018, 10, 29, AnnounceTime::Unknown)));
+    }
+
+    #[test]
+    fn nasdaq_after_market() {
+        assert_eq!(extract(extract_nasdaq, "NASDAQ", "AAPL").unwrap(), Some(EarningsDateTime::sample(2018, 10, 29, AnnounceTime::AfterMarket)));
+    }
+
+    #[test]
+    fn nasdaq_before_market() {
+        assert_eq!(extract(extract_nasdaq, "NASDAQ", "MSFT").unwrap(), Some(EarningsDateTime::sample(2019, 1, 24, AnnounceTime::BeforeMarket)));
+    }
+
+    #[test]
+    fn nasdaq_unknown_time() {
+        assert_eq!(extract(extract_nasdaq, "NASDAQ", "IBM").unwrap(), Some(EarningsDateTime::sample(2018, 10, 16, AnnounceTime::Unknown)));
+    }
+
+    #[test]
+    fn replay_fixtures() {
+        let logger = logger();
+        let mut settings = HashMap::new();
+        for name in &["Bloomberg", "NASDAQ"] {
+            settings.insert(name.to_string(), SourceSettings{ enabled: Some(true), ..SourceSettings::default() });
+        }
+
+        let sources = SourceRegistry::new(&logger, settings).unwrap();
+        let fetch = FetchMode::Replay(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures"));
+        let mut found = get_earnings_date_estimates(&logger, &as_of(), &sources, &fetch, "AAPL")
+            .into_iter()
+            .map(|d| d.source.into_owned())
+            .collect::<Vec<_>>();
+        found.sort();
+
+        assert_eq!(found, vec!["Bloomberg", "Estimize", "FinViz", "NASDAQ", "Yahoo", "Zacks"]);
+    }
+
+    fn weighted_sources(weights : &[(&str, f64)]) -> SourceRegistry {
+        let settings = weights.iter()
+            .map(|&(name, weight)| (name.to_string(), SourceSettings{ weight: weight, ..SourceSettings::default() }))
+            .collect();
+        SourceRegistry::new(&logger(), settings).unwrap()
+    }
+
+    #[test]
+    fn guess_skips_past_dates() {
+        let sources = weighted_sources(&[]);
+        let dates = vec![
+            SourcedEarningsTime::sample("FinViz", 2018, 7, 31, AnnounceTime::AfterMarket),
+            SourcedEarningsTime::sample("Zacks", 2018, 7, 31, AnnounceTime::AfterMarket),
+            SourcedEarningsTime::sample("CML", 2018, 10, 29, AnnounceTime::Unknown),
+        ];
+
+        let guess = best_earnings_guess(&Clock::AsOf(Date::from_ymd(2018, 7, 1)), &sources, &dates).unwrap();
+        assert_eq!(guess.last_session, Date::from_ymd(2018, 7, 31));
+
+        let guess = best_earnings_guess(&as_of(), &sources, &dates).unwrap();
+        assert_eq!(guess.last_session, Date::from_ymd(2018, 10, 26));
+    }
+
+    #[test]
+    fn guess_prefers_exact_time() {
+        let sources = weighted_sources(&[]);
+        let dates = vec![
+            SourcedEarningsTime::sample("FinViz", 2018, 10, 30, AnnounceTime::AfterMarket),
+            SourcedEarningsTime::sample("Yahoo", 2018, 10, 26, AnnounceTime::Unknown),
+        ];
+
+        let guess = best_earnings_guess(&as_of(), &sources, &dates).unwrap();
+        assert_eq!(guess.last_session, Date::from_ymd(2018, 10, 30));
+        assert_eq!(guess.confidence, 0.5);
+    }
+
+    #[test]
+    fn guess_confidence() {
+        let sources = weighted_sources(&[]);
+        let dates = vec![
+            SourcedEarningsTime::sample("FinViz", 2018, 10, 29, AnnounceTime::AfterMarket),
+            SourcedEarningsTime::sample("Zacks", 2018, 10, 30, AnnounceTime::BeforeMarket),
+            SourcedEarningsTime::sample("Estimize", 2018, 10, 29, AnnounceTime::AfterMarket),
+            SourcedEarningsTime::sample("CML", 2018, 10, 29, AnnounceTime::Unknown),
+        ];
+
+        let guess = best_earnings_guess(&as_of(), &sources, &dates).unwrap();
+        assert_eq!(guess.last_session, Date::from_ymd(2018, 10, 29));
+        assert_eq!(guess.confidence, 3.5 / 4.0);
+    }
+
+    #[test]
+    fn guess_uses_weights() {
+        let dates = vec![
+            SourcedEarningsTime::sample("FinViz", 2018, 10, 29, AnnounceTime::AfterMarket),
+            SourcedEarningsTime::sample("Zacks", 2018, 10, 30, AnnounceTime::AfterMarket),
+        ];
+
+        // A tie goes to the earlier date.
+        let guess = best_earnings_guess(&as_of(), &weighted_sources(&[]), &dates).unwrap();
+        assert_eq!(guess.last_session, Date::from_ymd(2018, 10, 29));
+
+        let guess = best_earnings_guess(&as_of(), &weighted_sources(&[("zacks", 2.0)]), &dates).unwrap();
+        assert_eq!(guess.last_session, Date::from_ymd(2018, 10, 30));
+        assert_eq!(guess.confidence, 2.0 / 3.0);
+    }
+
+    #[test]
+    fn guess_ignores_zero_weight() {
+        let dates = vec![SourcedEarningsTime::sample("CML", 2018, 10, 29, AnnounceTime::Unknown)];
+        assert!(best_earnings_guess(&as_of(), &weighted_sources(&[("CML", 0.0)]), &dates).is_none());
+    }
+
+    #[test]
+    fn trading_days_skip_holidays() {
+        // Thanksgiving week, 2018.
+        let monday = Date::from_ymd(2018, 11, 26);
+        assert_eq!(monday.add_trading_days(-1), Date::from_ymd(2018, 11, 23));
+        assert_eq!(monday.add_trading_days(-2), Date::from_ymd(2018, 11, 21));
+        assert_eq!(monday.add_trading_days(-7), Date::from_ymd(2018, 11, 14));
+        assert_eq!(Date::from_ymd(2018, 11, 21).add_trading_days(1), Date::from_ymd(2018, 11, 23));
+
+        // An offset of 0 steps back off a holiday.
+        assert_eq!(Date::from_ymd(2018, 11, 22).add_trading_days(0), Date::from_ymd(2018, 11, 21));
+        // The market was closed for President Bush's funeral on December 5.
+        assert_eq!(Date::from_ymd(2018, 12, 4).add_trading_days(1), Date::from_ymd(2018, 12, 6));
+    }
+
+    #[test]
+    fn pages_for_estimates() {
+        let guess = best_earnings_guess(&as_of(), &weighted_sources(&[]), &[
+            SourcedEarningsTime::sample("FinViz", 2018, 10, 29, AnnounceTime::AfterMarket),
+            SourcedEarningsTime::sample("Zacks", 2018, 11, 20, AnnounceTime::AfterMarket),
+            SourcedEarningsTime::sample("CML", 2018, 10, 29, AnnounceTime::Unknown),
+        ]).unwrap();
+
+        let pages = weighted_sources(&[]).pages("AAPL", &guess);
+        assert_eq!(pages.iter().map(|p| p.source.as_str()).collect::<Vec<_>>(), vec!["FinViz", "Zacks"]);
+        assert_eq!(pages[0].url, "https://finviz.com/quote.ashx?t=AAPL");
+    }
+}
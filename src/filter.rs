@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use failure::{Error, ResultExt};
+use cmlviz::{BacktestResult, Phase, Strategy};
+
+/// Minimum backtest quality. Anything left unset isn't checked.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BacktestFilter {
+    /// Wins plus losses.
+    pub min_trades : Option<usize>,
+    /// Percent.
+    pub min_win_rate : Option<i32>,
+    /// Percent.
+    pub min_avg_return : Option<i32>,
+    /// Percent.
+    pub min_total_return : Option<i32>,
+    /// Years.
+    pub min_backtest_length : Option<usize>,
+}
+
+impl BacktestFilter {
+    /// Each threshold set in `other` replaces this one's.
+    fn merge(&self, other : &BacktestFilter) -> BacktestFilter {
+        BacktestFilter{
+            min_trades: other.min_trades.or(self.min_trades),
+            min_win_rate: other.min_win_rate.or(self.min_win_rate),
+            min_avg_return: other.min_avg_return.or(self.min_avg_return),
+            min_total_return: other.min_total_return.or(self.min_total_return),
+            min_backtest_length: other.min_backtest_length.or(self.min_backtest_length),
+        }
+    }
+
+    /// Why the backtest doesn't pass, if it doesn't.
+    pub fn check(&self, test : &BacktestResult) -> Option<String> {
+        let trades = test.wins + test.losses;
+        if let Some(min) = self.min_trades.filter(|&min| trades < min) {
+            return Some(format!("{} trades, under {}", trades, min))
+        }
+        if let Some(min) = self.min_win_rate.filter(|&min| test.win_rate < min) {
+            return Some(format!("{}% win rate, under {}%", test.win_rate, min))
+        }
+        if let Some(min) = self.min_avg_return.filter(|&min| test.avg_trade_return < min) {
+            return Some(format!("{}% average return, under {}%", test.avg_trade_return, min))
+        }
+        if let Some(min) = self.min_total_return.filter(|&min| test.total_return < min) {
+            return Some(format!("{}% total return, under {}%", test.total_return, min))
+        }
+        if let Some(min) = self.min_backtest_length.filter(|&min| test.backtest_length < min) {
+            return Some(format!("{} year backtest, under {}", test.backtest_length, min))
+        }
+
+        None
+    }
+}
+
+/// Backtest filters from the settings file, keyed by `all`, a phase (`pre` or `post`), or a strategy ID. A strategy's own
+/// filter overrides its phase's, which overrides `all`, one threshold at a time.
+#[derive(Debug, Clone, Default)]
+pub struct BacktestFilters {
+    all : BacktestFilter,
+    pre : BacktestFilter,
+    post : BacktestFilter,
+    strategies : HashMap<Strategy, BacktestFilter>,
+}
+
+impl BacktestFilters {
+    /// Strategies must already be registered, so the keys can be checked.
+    pub fn new(settings : HashMap<String, BacktestFilter>) -> Result<BacktestFilters, Error> {
+        let mut filters = BacktestFilters::default();
+        for (key, filter) in settings {
+            match key.as_str() {
+                "all" => filters.all = filter,
+                "pre" => filters.pre = filter,
+                "post" => filters.post = filter,
+                _ => {
+                    let strategy = key.parse::<Strategy>().with_context(|_| format!("backtest filter {}", key))?;
+                    filters.strategies.insert(strategy, filter);
+                },
+            }
+        }
+
+        Ok(filters)
+    }
+
+    pub fn for_strategy(&self, strategy : Strategy) -> BacktestFilter {
+        let phase = match strategy.phase() {
+            Phase::Pre => &self.pre,
+            Phase::Post => &self.post,
+        };
+
+        let filter = self.all.merge(phase);
+        match self.strategies.get(&strategy) {
+            Some(own) => filter.merge(own),
+            None => filter,
+        }
+    }
+
+    /// Why the backtest doesn't pass its strategy's filter, if it doesn't.
+    pub fn check(&self, test : &BacktestResult) -> Option<String> {
+        self.for_strategy(test.strategy).check(test)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    fn test(strategy : &str, wins : usize, losses : usize, avg_trade_return : i32) -> BacktestResult {
+        BacktestResult::sample(strategy)
+            .with_trades(wins, losses)
+            .with_returns(avg_trade_return, avg_trade_return * (wins + losses) as i32)
+    }
+
+    #[test]
+    fn strategy_overrides_phase_overrides_all() {
+        let settings = serde_json::from_str(r#"{
+            "all": { "min_trades": 5, "min_avg_return": 10 },
+            "pre": { "min_avg_return": 20 },
+            "call_7d_preearnings": { "min_trades": 3 }
+        }"#).unwrap();
+        let filters = BacktestFilters::new(settings).unwrap();
+
+        assert_eq!(filters.check(&test("call_7d_preearnings", 3, 0, 25)), None);
+        assert_eq!(filters.check(&test("call_7d_preearnings", 3, 0, 15)), Some("15% average return, under 20%".to_string()));
+        assert_eq!(filters.check(&test("call_14d_preearnings", 3, 0, 25)), Some("3 trades, under 5".to_string()));
+        assert_eq!(filters.check(&test("iron_condor_post_earnings", 8, 4, 15)), None);
+    }
+
+    #[test]
+    fn unknown_strategy() {
+        let settings = serde_json::from_str(r#"{ "no_such_strategy": { "min_trades": 5 } }"#).unwrap();
+        assert!(BacktestFilters::new(settings).is_err());
+    }
+}
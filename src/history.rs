@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+use failure::{Error, ResultExt};
+use serde_json;
+use earnings::{AnnounceTime, Date, EarningsDateTime, EarningsGuess, SourcedEarningsTime};
+
+/// Realized dates this close to each other are taken to be the same announcement.
+pub const SAME_ANNOUNCEMENT_DAYS : i64 = 45;
+
+/// What a source said the earnings date was, and when it said it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Observation {
+    pub observed : Date,
+    pub estimate : SourcedEarningsTime,
+}
+
+/// When earnings were actually announced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Realized {
+    pub datetime : EarningsDateTime,
+    /// Entered with the `confirm` command, rather than taken from the backtest results.
+    pub confirmed : bool,
+}
+
+/// The consensus earnings date on the day it was first seen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuessRecord {
+    pub observed : Date,
+    pub guess : EarningsGuess,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SymbolHistory {
+    pub observations : Vec<Observation>,
+    /// Sorted by date.
+    pub realized : Vec<Realized>,
+    /// Each change in the consensus last session, oldest first.
+    pub guesses : Vec<GuessRecord>,
+}
+
+/// How often a source had the right date before earnings were announced.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Accuracy {
+    pub correct : usize,
+    pub total : usize,
+}
+
+impl Accuracy {
+    pub fn rate(&self) -> f64 {
+        if self.total == 0 { 0.0 } else { self.correct as f64 / self.total as f64 }
+    }
+}
+
+/// Every earnings date the sources have given, and the dates that were eventually announced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct History {
+    pub symbols : BTreeMap<String, SymbolHistory>,
+}
+
+impl History {
+    pub fn load<P : AsRef<Path>>(path : P) -> Result<History, Error> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|_| format!("opening {}", path.display()))?;
+        let history = serde_json::from_reader(file).with_context(|_| format!("parsing {}", path.display()))?;
+        Ok(history)
+    }
+
+    pub fn save<P : AsRef<Path>>(&self, path : P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|_| format!("creating {}", path.display()))?;
+        serde_json::to_writer(file, self).with_context(|_| format!("writing {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Save what each source said today. A source's earlier estimate from the same day is replaced.
+    pub fn observe(&mut self, symbol : &str, observed : Date, estimates : &[SourcedEarningsTime]) {
+        let history = self.symbols.entry(symbol.to_string()).or_insert_with(SymbolHistory::default);
+        for estimate in estimates {
+            history.observations.retain(|o| o.observed != observed || o.estimate.source != estimate.source);
+            history.observations.push(Observation{
+                observed: observed,
+                estimate: estimate.clone(),
+            });
+        }
+    }
+
+    /// Save the consensus for the symbol. If it moves the last session for the same announcement as the previous
+    /// consensus, the previous one is returned.
+    pub fn record_guess(&mut self, symbol : &str, observed : Date, guess : &EarningsGuess) -> Option<EarningsGuess> {
+        let history = self.symbols.entry(symbol.to_string()).or_insert_with(SymbolHistory::default);
+        let previous = history.guesses.last().map(|r| r.guess.clone());
+        if previous.as_ref().map_or(false, |p| p.last_session == guess.last_session) {
+            return None
+        }
+
+        history.guesses.push(GuessRecord{
+            observed: observed,
+            guess: guess.clone(),
+        });
+
+        // A new quarter's date isn't a move.
+        previous.filter(|p| p.last_session.signed_duration_since(guess.last_session).num_days().abs() <= SAME_ANNOUNCEMENT_DAYS)
+    }
+
+    /// Record when earnings were announced. An imported date doesn't replace a confirmed one for the same announcement,
+    /// but a confirmed date replaces anything.
+    pub fn realize(&mut self, symbol : &str, datetime : EarningsDateTime, confirmed : bool) {
+        let history = self.symbols.entry(symbol.to_string()).or_insert_with(SymbolHistory::default);
+        let existing = history.realized.iter()
+            .position(|r| r.datetime.date.signed_duration_since(datetime.date).num_days().abs() <= SAME_ANNOUNCEMENT_DAYS);
+
+        let realized = Realized{
+            datetime: datetime,
+            confirmed: confirmed,
+        };
+
+        match existing {
+            Some(index) => {
+                if confirmed || !history.realized[index].confirmed {
+                    history.realized[index] = realized;
+                }
+            },
+            None => history.realized.push(realized),
+        }
+
+        history.realized.sort_by_key(|r| r.datetime.date);
+    }
+
+    /// Score each source's last estimate before every realized announcement.
+    pub fn accuracy(&self) -> BTreeMap<String, Accuracy> {
+        let mut results = BTreeMap::new();
+        for history in self.symbols.values() {
+            let mut previous : Option<Date> = None;
+            for realized in history.realized.iter() {
+                // Observations made since the previous announcement were about this one.
+                let mut latest : BTreeMap<&str, &Observation> = BTreeMap::new();
+                let relevant = history.observations.iter()
+                    .filter(|o| o.observed <= realized.datetime.date && previous.map_or(true, |p| o.observed > p));
+                for observation in relevant {
+                    let entry = latest.entry(observation.estimate.source.as_ref()).or_insert(observation);
+                    if observation.observed > entry.observed {
+                        *entry = observation;
+                    }
+                }
+
+                for (source, observation) in latest {
+                    let accuracy = results.entry(source.to_string()).or_insert_with(Accuracy::default);
+                    accuracy.total += 1;
+                    if matches(&observation.estimate.datetime, &realized.datetime) {
+                        accuracy.correct += 1;
+                    }
+                }
+
+                previous = Some(realized.datetime.date);
+            }
+        }
+
+        results
+    }
+}
+
+/// An estimate is right if it has the right date, and the right time when both of them have one.
+fn matches(estimate : &EarningsDateTime, realized : &EarningsDateTime) -> bool {
+    if estimate.time == AnnounceTime::Unknown || realized.time == AnnounceTime::Unknown {
+        estimate.date == realized.date
+    } else {
+        estimate == realized
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirmed_dates_win() {
+        let mut history = History::default();
+        history.realize("AAPL", EarningsDateTime::sample(2018, 7, 31, AnnounceTime::AfterMarket), true);
+        history.realize("AAPL", EarningsDateTime::sample(2018, 7, 30, AnnounceTime::Unknown), false);
+        assert_eq!(history.symbols["AAPL"].realized.len(), 1);
+        assert_eq!(history.symbols["AAPL"].realized[0].datetime, EarningsDateTime::sample(2018, 7, 31, AnnounceTime::AfterMarket));
+
+        history.realize("AAPL", EarningsDateTime::sample(2018, 11, 1, AnnounceTime::Unknown), false);
+        history.realize("AAPL", EarningsDateTime::sample(2018, 11, 1, AnnounceTime::AfterMarket), true);
+        assert_eq!(history.symbols["AAPL"].realized.len(), 2);
+        assert_eq!(history.symbols["AAPL"].realized[1].datetime, EarningsDateTime::sample(2018, 11, 1, AnnounceTime::AfterMarket));
+    }
+
+    #[test]
+    fn guess_moves() {
+        let mut history = History::default();
+        assert!(history.record_guess("AAPL", Date::from_ymd(2018, 10, 1), &EarningsGuess::sample(Date::from_ymd(2018, 10, 29))).is_none());
+        assert!(history.record_guess("AAPL", Date::from_ymd(2018, 10, 8), &EarningsGuess::sample(Date::from_ymd(2018, 10, 29))).is_none());
+
+        let previous = history.record_guess("AAPL", Date::from_ymd(2018, 10, 15), &EarningsGuess::sample(Date::from_ymd(2018, 10, 31))).unwrap();
+        assert_eq!(previous.last_session, Date::from_ymd(2018, 10, 29));
+
+        // The next quarter
+        assert!(history.record_guess("AAPL", Date::from_ymd(2018, 11, 5), &EarningsGuess::sample(Date::from_ymd(2019, 1, 28))).is_none());
+        assert_eq!(history.symbols["AAPL"].guesses.len(), 3);
+    }
+
+    #[test]
+    fn accuracy_uses_latest_estimate() {
+        let mut history = History::default();
+        history.observe("AAPL", Date::from_ymd(2018, 7, 1), &[
+            SourcedEarningsTime::sample("FinViz", 2018, 7, 24, AnnounceTime::AfterMarket),
+            SourcedEarningsTime::sample("Zacks", 2018, 7, 31, AnnounceTime::AfterMarket),
+        ]);
+        history.observe("AAPL", Date::from_ymd(2018, 7, 20), &[
+            SourcedEarningsTime::sample("FinViz", 2018, 7, 31, AnnounceTime::AfterMarket),
+            SourcedEarningsTime::sample("Zacks", 2018, 7, 31, AnnounceTime::BeforeMarket),
+            SourcedEarningsTime::sample("CML", 2018, 7, 31, AnnounceTime::Unknown),
+        ]);
+        // Made after the announcement, so it's about the next one.
+        history.observe("AAPL", Date::from_ymd(2018, 8, 2), &[
+            SourcedEarningsTime::sample("FinViz", 2018, 10, 29, AnnounceTime::AfterMarket),
+        ]);
+        history.realize("AAPL", EarningsDateTime::sample(2018, 7, 31, AnnounceTime::AfterMarket), true);
+
+        let accuracy = history.accuracy();
+        assert_eq!((accuracy["FinViz"].correct, accuracy["FinViz"].total), (1, 1));
+        assert_eq!((accuracy["Zacks"].correct, accuracy["Zacks"].total), (0, 1));
+        assert_eq!((accuracy["CML"].correct, accuracy["CML"].total), (1, 1));
+    }
+}
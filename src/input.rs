@@ -0,0 +1,396 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use csv;
+use failure::{Error, ResultExt};
+use regex::{self, Regex};
+use slog;
+use cmlviz::{BacktestResult, BacktestResultInput, FieldError, Strategy};
+use datatables;
+use earnings::EarningsDateTime;
+
+static WILDCARDS : &[char] = &['*', '?'];
+
+/// Everything about a backtest except the earnings dates, which change from week to week.
+type BacktestKey = (String, Strategy, usize, usize, i32, i32, i32, usize);
+
+fn key(test : &BacktestResult) -> BacktestKey {
+    (test.symbol.clone(), test.strategy, test.wins, test.losses, test.win_rate, test.avg_trade_return, test.total_return,
+        test.backtest_length)
+}
+
+/// Expand `*` and `?` in the file name part of each pattern, in sorted order. Patterns without them are used as they
+/// are. This is for when the shell doesn't expand them, as on Windows or when they're quoted.
+pub fn expand_paths(patterns : &[String]) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        if !pattern.contains(WILDCARDS) {
+            paths.push(PathBuf::from(pattern));
+            continue
+        }
+
+        let path = Path::new(pattern);
+        let parent = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        let dir = parent.unwrap_or_else(|| Path::new("."));
+        if dir.to_string_lossy().contains(WILDCARDS) {
+            bail!("{}: wildcards are only supported in file names", pattern);
+        }
+
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let re = name.split('*')
+            .map(|part| part.split('?').map(regex::escape).collect::<Vec<_>>().join("."))
+            .collect::<Vec<_>>()
+            .join(".*");
+        let re = Regex::new(&format!("^{}$", re))?;
+
+        let mut matches = Vec::new();
+        for entry in fs::read_dir(dir).with_context(|_| format!("listing {}", dir.display()))? {
+            let entry = entry?;
+            if re.is_match(&entry.file_name().to_string_lossy()) {
+                matches.push(parent.map_or_else(|| PathBuf::from(entry.file_name()), |dir| dir.join(entry.file_name())));
+            }
+        }
+
+        if matches.is_empty() {
+            bail!("{} didn't match any files", pattern);
+        }
+
+        matches.sort();
+        paths.extend(matches);
+    }
+
+    Ok(paths)
+}
+
+/// The strategy a single-strategy export is for, from its file name. With a pattern, its first capture group is the
+/// strategy ID. Otherwise it's the longest strategy ID in the file name.
+pub fn infer_strategy(path : &Path, pattern : Option<&Regex>) -> Option<Strategy> {
+    let name = path.file_stem()?.to_string_lossy();
+    match pattern {
+        Some(pattern) => pattern.captures(&name)
+            .and_then(|captures| captures.get(1))
+            .and_then(|m| m.as_str().parse().ok()),
+        None => Strategy::all()
+            .into_iter()
+            .filter(|strategy| name.contains(strategy.id()))
+            .max_by_key(|strategy| strategy.id().len()),
+    }
+}
+
+/// A row that couldn't be read.
+#[derive(Debug, Clone)]
+pub struct Reject {
+    pub path : PathBuf,
+    /// The line in a CSV file, or the row in a JSON file.
+    pub line : u64,
+    pub field : Option<String>,
+    pub message : String,
+    /// The row's cells as they were read, if they could be.
+    pub cells : Vec<String>,
+}
+
+impl fmt::Display for Reject {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} line {}: ", self.path.display(), self.line)?;
+        if let Some(ref field) = self.field {
+            write!(f, "{}: ", field)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// The backtests read from the input files, and the rows that couldn't be read.
+#[derive(Debug, Default)]
+pub struct Backtests {
+    pub tests : Vec<BacktestResult>,
+    pub rejects : Vec<Reject>,
+}
+
+/// A row from an input file, before it's been checked.
+struct Row {
+    line : u64,
+    cells : Vec<String>,
+    /// The field and message when the row couldn't be read at all.
+    input : Result<BacktestResultInput, (Option<String>, String)>,
+}
+
+fn is_json(path : &Path) -> bool {
+    path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("json"))
+}
+
+fn read_json(path : &Path, columns : &[String]) -> Result<Vec<Row>, Error> {
+    let rows = datatables::read(path, columns)?
+        .into_iter()
+        .enumerate()
+        .map(|(index, row)| Row{
+            line: index as u64 + 1,
+            cells: row.cells,
+            input: row.input.map_err(|e| (Some(e.field.to_string()), e.message)),
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+/// Only a file that can't be opened fails. Rows that can't be read are returned as errors.
+fn read_csv(path : &Path) -> Result<Vec<Row>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_path(path)
+        .with_context(|_| format!("opening {}", path.display()))?;
+    let headers = reader.headers().with_context(|_| format!("reading {}", path.display()))?.clone();
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let row = match record {
+            Ok(record) => {
+                let input = record.deserialize::<BacktestResultInput>(Some(&headers))
+                    .map_err(|e| match *e.kind() {
+                        csv::ErrorKind::Deserialize{ ref err, .. } => {
+                            // Errors from the strategy's own parser don't say which field they came from.
+                            let field = err.field()
+                                .and_then(|index| headers.get(index as usize))
+                                .or_else(|| {
+                                    headers.iter().position(|header| header == "strategy")
+                                        .and_then(|index| record.get(index))
+                                        .filter(|id| !id.is_empty() && id.parse::<Strategy>().is_err())
+                                        .map(|_| "strategy")
+                                })
+                                .map(String::from);
+                            (field, err.kind().to_string())
+                        },
+                        _ => (None, e.to_string()),
+                    });
+
+                Row{
+                    line: record.position().map_or(0, |p| p.line()),
+                    cells: record.iter().map(String::from).collect(),
+                    input: input,
+                }
+            },
+            Err(e) => {
+                let line = match *e.kind() {
+                    csv::ErrorKind::Utf8{ pos: Some(ref pos), .. } | csv::ErrorKind::UnequalLengths{ pos: Some(ref pos), .. } => pos.line(),
+                    _ => 0,
+                };
+
+                Row{
+                    line: line,
+                    cells: Vec::new(),
+                    input: Err((None, e.to_string())),
+                }
+            },
+        };
+
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// Read the backtests from every input file, which can be CSV or, with a `.json` extension, TradeMachine's DataTables
+/// JSON, with array rows in the order of `json_columns`. Rows repeated in more than one file are only kept once. Rows
+/// that can't be read are returned as rejects, instead of failing the whole file.
+pub fn read_backtests(logger : &slog::Logger, paths : &[PathBuf], strategy_pattern : Option<&Regex>, json_columns : &[String])
+    -> Result<Backtests, Error> {
+    let mut seen = HashSet::new();
+    // The first file to give each symbol's next earnings date, and the date.
+    let mut earnings : HashMap<String, (&Path, EarningsDateTime)> = HashMap::new();
+    let mut disagreements = HashSet::new();
+    let mut backtests = Backtests::default();
+
+    for path in paths {
+        info!(logger, "Reading file {}", path.display());
+        let inferred = infer_strategy(path, strategy_pattern);
+
+        let rows = if is_json(path) { read_json(path, json_columns)? } else { read_csv(path)? };
+
+        let mut duplicates = 0;
+        for row in rows {
+            let parsed = row.input.and_then(|mut input| {
+                if input.strategy.is_none() {
+                    input.strategy = inferred;
+                }
+
+                BacktestResult::from_input(input).map_err(|e| {
+                    match e.downcast_ref::<FieldError>() {
+                        Some(e) => (Some(e.field.to_string()), e.message.clone()),
+                        None => (None, e.to_string()),
+                    }
+                })
+            });
+
+            let test = match parsed {
+                Ok(test) => test,
+                Err((field, message)) => {
+                    backtests.rejects.push(Reject{
+                        path: path.clone(),
+                        line: row.line,
+                        field: field,
+                        message: message,
+                        cells: row.cells,
+                    });
+                    continue
+                },
+            };
+
+            match earnings.get(&test.symbol) {
+                Some(&(other_path, datetime)) => {
+                    if datetime != test.next_earnings && disagreements.insert((test.symbol.clone(), path.as_path())) {
+                        if other_path == path.as_path() {
+                            warn!(logger, "{} has {} earnings on both {} and {}",
+                                path.display(), test.symbol, datetime, test.next_earnings);
+                        } else {
+                            warn!(logger, "{} has {} earnings on {}, but {} has {}",
+                                other_path.display(), test.symbol, datetime, path.display(), test.next_earnings);
+                        }
+                    }
+                },
+                None => {
+                    earnings.insert(test.symbol.clone(), (path.as_path(), test.next_earnings));
+                },
+            }
+
+            if seen.insert(key(&test)) {
+                backtests.tests.push(test);
+            } else {
+                duplicates += 1;
+            }
+        }
+
+        if duplicates > 0 {
+            info!(logger, "Skipped {} rows from {} that were already read", duplicates, path.display());
+        }
+    }
+
+    Ok(backtests)
+}
+
+/// Save rejected rows as CSV, with where each one came from and why it was rejected ahead of its original cells.
+pub fn write_rejects(path : &str, rejects : &[Reject]) -> Result<(), Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_path(path)
+        .with_context(|_| format!("creating {}", path))?;
+
+    writer.write_record(&["file", "line", "field", "error"])?;
+    for reject in rejects {
+        let mut record = vec![
+            reject.path.display().to_string(),
+            reject.line.to_string(),
+            reject.field.clone().unwrap_or_default(),
+            reject.message.clone(),
+        ];
+        record.extend(reject.cells.iter().cloned());
+        writer.write_record(&record)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{logger, recording_logger, temp_path};
+
+    static HEADER : &str = "symbol,wins,losses,win_rate,avg_trade_return,total_return,backtest_length,next_earnings,prev_earnings_result,strategy";
+
+    /// Read the files, which are given as rows under the usual header, and return the warnings about earnings dates.
+    fn earnings_warnings(files : &[(&str, &[&str])]) -> Vec<String> {
+        let paths = files.iter()
+            .map(|&(name, rows)| {
+                let path = temp_path(name);
+                fs::write(&path, format!("{}\n{}\n", HEADER, rows.join("\n"))).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+
+        let (logger, messages) = recording_logger();
+        read_backtests(&logger, &paths, None, &datatables::default_columns()).unwrap();
+        for path in paths.iter() {
+            fs::remove_file(path).unwrap();
+        }
+
+        let messages = messages.lock().unwrap();
+        messages.iter().filter(|m| m.contains(" earnings on ")).cloned().collect()
+    }
+
+    #[test]
+    fn infers_strategy_from_file_name() {
+        let strategy = infer_strategy(Path::new("exports/2018-10-01 call_14d_preearnings.csv"), None).unwrap();
+        assert_eq!(strategy.id(), "call_14d_preearnings");
+
+        let pattern = Regex::new(r"^cml-(\w+)-\d+$").unwrap();
+        let strategy = infer_strategy(Path::new("cml-iron_condor_post_earnings-20181001.csv"), Some(&pattern)).unwrap();
+        assert_eq!(strategy.id(), "iron_condor_post_earnings");
+
+        assert!(infer_strategy(Path::new("backtests.csv"), None).is_none());
+    }
+
+    #[test]
+    fn expands_wildcards() {
+        let dir = temp_path("wildcards");
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["AAPL.csv", "ABPL.json", "AAAPL.csv", "MSFT.csv"].iter() {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let pattern = dir.join("A?PL.*").to_string_lossy().into_owned();
+        let expanded = expand_paths(&[pattern]);
+        let missing = expand_paths(&[dir.join("*.txt").to_string_lossy().into_owned()]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(expanded.unwrap(), vec![dir.join("AAPL.csv"), dir.join("ABPL.json")]);
+        assert!(missing.is_err());
+
+        let literal = vec!["no-such-file.csv".to_string()];
+        assert_eq!(expand_paths(&literal).unwrap(), vec![PathBuf::from(&literal[0])]);
+    }
+
+    #[test]
+    fn rejects_bad_rows() {
+        let path = temp_path("bad-rows.csv");
+        fs::write(&path, "symbol,wins,losses,win_rate,avg_trade_return,total_return,backtest_length,next_earnings,prev_earnings_result,strategy
+AAPL,10,2,83%,25%,300%,3,2018‑10‑29 Not Verified,2018‑07‑31,call_7d_preearnings
+IBM,five,5,50%,10%,50%,3,2018-10-29,,call_7d_preearnings
+IBM,5,5,50%,10%,50%,3,someday,,call_7d_preearnings
+IBM,5,5,50%,10%,50%,3,2018-10-29,,no_such
+IBM,5,5
+").unwrap();
+
+        let paths = vec![path];
+        let backtests = read_backtests(&logger(), &paths, None, &datatables::default_columns()).unwrap();
+        fs::remove_file(&paths[0]).unwrap();
+
+        assert_eq!(backtests.tests.len(), 1);
+        assert_eq!(backtests.tests[0].symbol, "AAPL");
+
+        let rejects = backtests.rejects.iter()
+            .map(|reject| (reject.line, reject.field.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(rejects, vec![(3, Some("wins")), (4, Some("next_earnings")), (5, Some("strategy")), (6, None)]);
+        assert_eq!(backtests.rejects[0].cells[1], "five");
+    }
+
+    #[test]
+    fn warns_of_disagreements_within_a_file() {
+        let warnings = earnings_warnings(&[("within.csv", &[
+            "AAPL,10,2,83%,25%,300%,3,2018-10-29,2018-07-31,call_7d_preearnings",
+            "AAPL,10,2,83%,25%,300%,3,2018-10-30,2018-07-31,call_14d_preearnings",
+        ])]);
+        let path = temp_path("within.csv");
+        assert_eq!(warnings, vec![format!("{} has AAPL earnings on both 2018-10-29 and 2018-10-30", path.display())]);
+    }
+
+    #[test]
+    fn warns_of_disagreements_between_files() {
+        let warnings = earnings_warnings(&[
+            ("first.csv", &["AAPL,10,2,83%,25%,300%,3,2018-10-29,2018-07-31,call_7d_preearnings"]),
+            ("second.csv", &["AAPL,10,2,83%,25%,300%,3,2018-10-30,2018-07-31,call_14d_preearnings"]),
+        ]);
+        assert_eq!(warnings, vec![format!("{} has AAPL earnings on 2018-10-29, but {} has 2018-10-30",
+            temp_path("first.csv").display(), temp_path("second.csv").display())]);
+    }
+}
@@ -21,20 +21,32 @@ extern crate clap;
 extern crate structopt;
 #[macro_use] extern crate structopt_derive;
 
+mod cache;
+mod calendar;
+mod clock;
 mod cmlviz;
+mod config;
+mod datatables;
+mod diff;
 mod earnings;
-
-use failure::{Error, ResultExt};
+mod filter;
+mod history;
+mod input;
+mod output;
+mod ranking;
+#[cfg(test)]
+mod testing;
+
+use failure::{Error, ResultExt, err_msg};
 use itertools::Itertools;
-use std::io::Write;
+use std::io::{BufRead, BufReader, Write};
 use std::fs::File;
 use sloggers::Build;
 use sloggers::terminal::TerminalLoggerBuilder;
 use std::collections::{HashMap, BTreeMap};
-use reqwest::header::{Headers, UserAgent};
 use structopt::StructOpt;
 
-static EARNINGS_CACHE_NAME : &'static str = ".earnings_cache.json";
+static EARNINGS_HISTORY_NAME : &'static str = ".earnings_history.json";
 
 fn init_logger() -> slog::Logger {
     TerminalLoggerBuilder::new()
@@ -57,14 +69,29 @@ struct Config {
     #[structopt(long="save-raw", help="Save the raw data to a JSON file")]
     save_raw : Option<String>,
 
-    #[structopt(help = "Input file")]
-    input : String,
+    #[structopt(help = "Input files, which can include * and ? wildcards")]
+    input : Vec<String>,
+
+    #[structopt(long="rejects", help="Save input rows that couldn't be read to this CSV file")]
+    rejects : Option<String>,
+
+    #[structopt(long="strict", help="Fail if any input rows couldn't be read")]
+    strict : bool,
+
+    #[structopt(long="strategy-pattern", help="Regex whose first group is the strategy ID in input file names, for files without a strategy column")]
+    strategy_pattern : Option<regex::Regex>,
 
     #[structopt(long="output", short="o", help="Output file")]
     output : Option<String>,
 
+    #[structopt(long="format", help="Output format: text, json, csv, ics, html, markdown or actions", default_value="text")]
+    format : output::Format,
+
+    #[structopt(long="event-time", help="Market time of day (HH:MM, New York) for calendar events, instead of all-day events", parse(try_from_str="parse_time"))]
+    event_time : Option<chrono::NaiveTime>,
+
     #[structopt(long="strategy", short="s", help="Strategies to include")]
-    strategies : Vec<cmlviz::Strategy>,
+    strategies : Vec<String>,
 
     #[structopt(long="post", help="Include only post-earnings strategies (and default to --best if not otherwise specified)")]
     post_earnings : bool,
@@ -77,30 +104,422 @@ struct Config {
 
     #[structopt(long="all", help="One row per active strategy")]
     all : bool,
+
+    #[structopt(long="closures", help="File of extra market closure dates, one YYYY-MM-DD per line, optionally followed by an HH:MM early close time")]
+    closures : Option<String>,
+
+    #[structopt(long="config", help="JSON settings file")]
+    config : Option<String>,
+
+    #[structopt(long="record", help="Save every fetched earnings page under this directory", conflicts_with="replay")]
+    record : Option<String>,
+
+    #[structopt(long="replay", help="Read earnings pages saved with --record instead of fetching them")]
+    replay : Option<String>,
+
+    #[structopt(long="day-count", help="Count strategy offsets in trading sessions or calendar days", default_value="trading")]
+    day_count : cmlviz::DayCount,
+
+    #[structopt(long="rank-by", help="How to pick the best backtest: weighted, avg-return, wilson, shrunk-return or annual-return", default_value="weighted")]
+    rank_by : ranking::Ranking,
+
+    #[structopt(long="as-of", help="Plan as if today were this date (YYYY-MM-DD)")]
+    as_of : Option<earnings::Date>,
+
+    #[structopt(long="min-confidence", help="Skip symbols whose earnings date confidence is below this percentage")]
+    min_confidence : Option<f64>,
+
+    #[structopt(long="weight-by-accuracy", help="Scale each earnings source's weight by how often it has been right")]
+    weight_by_accuracy : bool,
+
+    #[structopt(long="no-cache", help="Fetch every earnings date, and leave the earnings cache alone", conflicts_with="refresh")]
+    no_cache : bool,
+
+    #[structopt(long="refresh", help="Fetch every earnings date, and save them to the earnings cache")]
+    refresh : bool,
+
+    #[structopt(long="cache-path", help="Earnings cache file, instead of the one in the XDG data directory")]
+    cache_path : Option<String>,
+
+    #[structopt(long="cache-ttl", help="Fetch cached earnings dates again after this many days", default_value="7")]
+    cache_ttl : i64,
+
+    #[structopt(subcommand)]
+    command : Option<Command>,
 }
 
-#[derive(Debug,Serialize)]
+fn parse_time(s : &str) -> Result<chrono::NaiveTime, chrono::ParseError> {
+    chrono::NaiveTime::parse_from_str(s, "%H:%M")
+}
+
+#[derive(StructOpt)]
+enum Command {
+    #[structopt(name="confirm", about="Record the date a symbol actually announced earnings")]
+    Confirm {
+        #[structopt(help="Symbol")]
+        symbol : String,
+
+        #[structopt(help="Announcement date")]
+        date : earnings::Date,
+
+        #[structopt(long="time", help="amc or bmo, if known")]
+        time : Option<earnings::AnnounceTime>,
+    },
+
+    #[structopt(name="accuracy", about="Show how often each earnings source had the right date")]
+    Accuracy,
+
+    #[structopt(name="today", about="Show the positions to open and close today, from plans saved with --save-raw")]
+    Today {
+        #[structopt(help="Saved plans, oldest first")]
+        plans : Vec<String>,
+    },
+
+    #[structopt(name="diff", about="Show what changed between two plans saved with --save-raw")]
+    Diff {
+        #[structopt(help="The earlier plan")]
+        old : String,
+
+        #[structopt(help="The later plan")]
+        new : String,
+    },
+
+    #[structopt(name="cache", about="Manage the cached earnings dates")]
+    Cache {
+        #[structopt(subcommand)]
+        command : CacheCommand,
+    },
+}
+
+#[derive(StructOpt)]
+enum CacheCommand {
+    #[structopt(name="list", about="List the cached earnings dates")]
+    List,
+
+    #[structopt(name="show", about="Show everything cached for a symbol")]
+    Show {
+        #[structopt(help="Symbol")]
+        symbol : String,
+    },
+
+    #[structopt(name="invalidate", about="Remove symbols from the cache, so they're fetched again on the next run")]
+    Invalidate {
+        #[structopt(help="Symbols")]
+        symbols : Vec<String>,
+    },
+
+    #[structopt(name="prune", about="Remove the cached dates that would be fetched again anyway")]
+    Prune,
+
+    #[structopt(name="refresh", about="Fetch the earnings dates for symbols again now")]
+    Refresh {
+        #[structopt(help="Symbols")]
+        symbols : Vec<String>,
+    },
+}
+
+#[derive(Debug,Serialize,Deserialize)]
 struct TestsAndEarnings {
     symbol : String,
     tests : Vec<cmlviz::BacktestResult>,
     active_test_index: usize,
     earnings : earnings::EarningsGuess,
+    // Plans saved before these were added don't have them.
+    #[serde(default)]
+    open_date : Option<earnings::Date>,
+    #[serde(default)]
+    close_date : Option<earnings::Date>,
+}
+
+impl TestsAndEarnings {
+    fn trade(&self, day_count : cmlviz::DayCount, ranking : ranking::Ranking, source_pages : Vec<earnings::SourcePage>) -> output::Trade {
+        let strategy = self.tests[self.active_test_index].strategy;
+        let open_date = self.open_date.unwrap_or_else(|| strategy.open_date(self.earnings.last_session, day_count));
+        let close_date = self.close_date.unwrap_or_else(|| strategy.close_date(self.earnings.last_session, day_count));
+        output::Trade::new(open_date, close_date, &self.tests, self.active_test_index, &self.earnings, source_pages, ranking)
+    }
+}
+
+/// Read a plan saved with `--save-raw`.
+fn load_saved_plan(path : &str) -> Result<Vec<TestsAndEarnings>, Error> {
+    let file = File::open(path).with_context(|_| format!("opening {}", path))?;
+    let mut plan = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|_| format!("reading {}", path))?;
+        if line.trim().is_empty() {
+            continue
+        }
+
+        let data = serde_json::from_str(&line).with_context(|_| format!("{} line {}", path, index + 1))?;
+        plan.push(data);
+    }
+
+    Ok(plan)
+}
+
+fn open_output(path : &Option<String>) -> Result<Box<Write>, Error> {
+    let output = path.as_ref()
+        .map(|path| {
+            let b = Box::new(File::create(path)?);
+            let r : Result<Box<Write>, std::io::Error> = Ok(b);
+            r
+        })
+        .unwrap_or_else(|| Ok(Box::new(std::io::stdout())))
+        .context("Opening output file")?;
+    Ok(output)
 }
 
-fn run_it(logger : &slog::Logger) -> Result<(), Error> {
-    let mut cfg = Config::from_args();
-    let filename = &cfg.input;
+/// A history that can't be read is an error, since saving over it would lose every observation in it.
+fn load_history() -> Result<history::History, Error> {
+    if !std::path::Path::new(EARNINGS_HISTORY_NAME).exists() {
+        return Ok(history::History::default())
+    }
+
+    let history = history::History::load(EARNINGS_HISTORY_NAME).context("loading earnings history")?;
+    Ok(history)
+}
+
+fn fetch_mode(cfg : &Config) -> earnings::FetchMode {
+    match (cfg.record.as_ref(), cfg.replay.as_ref()) {
+        (Some(dir), _) => earnings::FetchMode::Record(dir.into()),
+        (_, Some(dir)) => earnings::FetchMode::Replay(dir.into()),
+        _ => earnings::FetchMode::Live,
+    }
+}
+
+fn cache_path(cfg : &Config) -> std::path::PathBuf {
+    cfg.cache_path.as_ref().map_or_else(cache::default_path, std::path::PathBuf::from)
+}
+
+/// Caches from before the location was configurable are picked up from the working directory, and saved to the new
+/// location.
+fn load_cache(logger : &slog::Logger, path : &std::path::Path) -> Result<cache::EarningsCache, Error> {
+    let legacy = std::path::Path::new(cache::LEGACY_PATH);
+    if !path.exists() && legacy.exists() {
+        info!(logger, "Moving earnings cache from {} to {}", legacy.display(), path.display());
+        return cache::EarningsCache::load(logger, legacy)
+    }
+
+    cache::EarningsCache::load(logger, path)
+}
+
+/// Fetch every source's estimate for the symbol, add them to the history, and work out the consensus. The date from the
+/// backtest results counts as one more estimate.
+fn fetch_guess(logger : &slog::Logger, clock : &clock::Clock, sources : &earnings::SourceRegistry, fetch : &earnings::FetchMode,
+    history : &mut history::History, symbol : &str, test_date : Option<earnings::EarningsDateTime>) -> Option<earnings::EarningsGuess> {
+
+    let mut earnings_dates = earnings::get_earnings_date_estimates(logger, clock, sources, fetch, symbol);
+    if let Some(datetime) = test_date {
+        earnings_dates.push(earnings::SourcedEarningsTime{
+            source: cmlviz::SOURCE_NAME.into(),
+            datetime: datetime,
+        });
+    }
+    history.observe(symbol, clock.today(), &earnings_dates);
+    earnings::best_earnings_guess(clock, sources, &earnings_dates)
+}
+
+/// Add the guess to the history, warning if the earnings date moved. Returns the guess it moved from.
+fn record_guess(logger : &slog::Logger, history : &mut history::History, symbol : &str, today : earnings::Date,
+    guess : &earnings::EarningsGuess) -> Option<earnings::EarningsGuess> {
+
+    let moved_from = history.record_guess(symbol, today, guess);
+    if let Some(ref previous) = moved_from {
+        warn!(logger, "Last session before {} earnings moved from {} to {}", symbol, previous.last_session, guess.last_session);
+    }
+    moved_from
+}
+
+fn run_cache_command(logger : &slog::Logger, cfg : &Config, clock : clock::Clock,
+    source_settings : HashMap<String, earnings::SourceSettings>, command : &CacheCommand) -> Result<(), Error> {
+
+    let path = cache_path(cfg);
+    let mut cache = load_cache(logger, &path)?;
+    let ttl = chrono::Duration::days(cfg.cache_ttl);
+    let today = clock.today();
+
+    match *command {
+        CacheCommand::List => {
+            let mut output = open_output(&cfg.output)?;
+            writeln!(output, "{:<8} {:<12} {:>10}  {:<16}  Status", "Symbol", "Last Session", "Confidence", "Fetched")?;
+            for (symbol, entry) in cache.entries.iter() {
+                let status = if cache::is_fresh(entry, &clock, ttl) { "fresh" } else { "stale" };
+                writeln!(output, "{:<8} {:<12} {:>9.0}%  {:<16}  {}",
+                    symbol,
+                    entry.guess.last_session.to_string(),
+                    entry.guess.confidence * 100.0,
+                    entry.fetched.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string(),
+                    status)?;
+            }
+        },
+        CacheCommand::Show{ ref symbol } => {
+            let entry = cache.entries.get(symbol).ok_or_else(|| format_err!("{} isn't cached", symbol))?;
+            let mut output = open_output(&cfg.output)?;
+            serde_json::to_writer_pretty(&mut output, entry)?;
+            writeln!(output)?;
+        },
+        CacheCommand::Invalidate{ ref symbols } => {
+            for symbol in symbols {
+                if cache.remove(symbol) {
+                    info!(logger, "Removed {} from the earnings cache", symbol);
+                } else {
+                    warn!(logger, "{} isn't cached", symbol);
+                }
+            }
+            cache.save(&path)?;
+        },
+        CacheCommand::Prune => {
+            let pruned = cache.prune(&clock, ttl);
+            info!(logger, "Pruned {} stale earnings dates: {}", pruned.len(), pruned.join(", "));
+            cache.save(&path)?;
+        },
+        CacheCommand::Refresh{ ref symbols } => {
+            let sources = earnings::SourceRegistry::new(logger, source_settings).context("setting up earnings sources")?;
+            let fetch = fetch_mode(cfg);
+            let mut history = load_history()?;
+            for symbol in symbols {
+                // There's no input file, so keep the backtest results' date from the cached guess.
+                let test_date = cache.entries.get(symbol)
+                    .and_then(|entry| {
+                        let guess = &entry.guess;
+                        guess.concurrences.iter()
+                            .chain(guess.close_disagreements.iter())
+                            .chain(guess.far_disagreements.iter())
+                            .find(|e| e.source == cmlviz::SOURCE_NAME)
+                            .map(|e| e.datetime)
+                    });
+
+                match fetch_guess(logger, &clock, &sources, &fetch, &mut history, symbol, test_date) {
+                    Some(guess) => {
+                        record_guess(logger, &mut history, symbol, today, &guess);
+                        info!(logger, "Last session before {} earnings is {}", symbol, guess.last_session);
+                        cache.insert(symbol, guess, chrono::Utc::now());
+                    },
+                    None => warn!(logger, "Couldn't find an earnings date for {}", symbol),
+                }
+            }
+            cache.save(&path)?;
+            history.save(EARNINGS_HISTORY_NAME)?;
+        },
+    }
+
+    Ok(())
+}
+
+fn run_command(logger : &slog::Logger, cfg : &Config, clock : clock::Clock,
+    source_settings : HashMap<String, earnings::SourceSettings>, command : &Command) -> Result<(), Error> {
+    match *command {
+        Command::Confirm{ ref symbol, date, time } => {
+            let mut history = load_history()?;
+            let datetime = earnings::EarningsDateTime{
+                date: date,
+                time: time.unwrap_or(earnings::AnnounceTime::Unknown),
+            };
+            history.realize(symbol, datetime, true);
+            history.save(EARNINGS_HISTORY_NAME)?;
+            info!(logger, "Confirmed {} earnings on {}", symbol, datetime);
+        },
+        Command::Accuracy => {
+            let history = load_history()?;
+            let mut output = open_output(&cfg.output)?;
+            writeln!(output, "{:<12} {:>8} {:>8} {:>9}", "Source", "Correct", "Total", "Accuracy")?;
+            for (source, accuracy) in history.accuracy() {
+                writeln!(output, "{:<12} {:>8} {:>8} {:>8.0}%", source, accuracy.correct, accuracy.total, accuracy.rate() * 100.0)?;
+            }
+        },
+        Command::Today{ ref plans } => {
+            if plans.is_empty() {
+                return Err(err_msg("At least one saved plan is required"));
+            }
+
+            // A newer plan's trade replaces an older one for the same symbol, strategy and announcement. Trades that
+            // only appear in older plans are kept, since they may still need to be closed.
+            let mut trades : Vec<output::Trade> = Vec::new();
+            for path in plans {
+                for data in load_saved_plan(path)? {
+                    let trade = data.trade(cfg.day_count, cfg.rank_by, Vec::new());
+                    let existing = trades.iter().position(|t| {
+                        t.symbol == trade.symbol && t.test.strategy == trade.test.strategy &&
+                            t.earnings.last_session.signed_duration_since(trade.earnings.last_session).num_days().abs() <= history::SAME_ANNOUNCEMENT_DAYS
+                    });
+
+                    match existing {
+                        Some(index) => trades[index] = trade,
+                        None => trades.push(trade),
+                    }
+                }
+            }
+
+            let plan = output::Plan{
+                as_of: clock.today(),
+                generated: chrono::Utc::now(),
+                trades: trades,
+            };
+            let mut output = open_output(&cfg.output)?;
+            output::write(output::Format::Actions, &output::Options::default(), &mut output, &plan)?;
+        },
+        Command::Diff{ ref old, ref new } => {
+            let load = |path| -> Result<Vec<output::Trade>, Error> {
+                Ok(load_saved_plan(path)?.iter().map(|data| data.trade(cfg.day_count, cfg.rank_by, Vec::new())).collect())
+            };
+
+            let changes = diff::diff(&load(old)?, &load(new)?, cfg.rank_by);
+            let mut output = open_output(&cfg.output)?;
+            if changes.is_empty() {
+                writeln!(output, "No changes")?;
+            }
+            for change in changes {
+                writeln!(output, "{}", change)?;
+            }
+        },
+        Command::Cache{ ref command } => run_cache_command(logger, cfg, clock, source_settings, command)?,
+    }
+
+    Ok(())
+}
+
+fn run_it(logger : &slog::Logger, cfg : Config) -> Result<(), Error> {
+    let clock = cfg.as_of.map_or(clock::Clock::System, clock::Clock::AsOf);
+
+    // Load these first, since saved plans can refer to strategies from the settings file.
+    let settings = match cfg.config {
+        Some(ref path) => config::Settings::load(path)?,
+        None => config::Settings::default(),
+    };
+    cmlviz::register_strategies(settings.strategies).context("loading strategies")?;
+
+    // Commands that work from saved plans work out dates too.
+    if let Some(ref path) = cfg.closures {
+        let count = calendar::load_closures(path).context("loading market closures")?;
+        info!(logger, "Loaded {} extra market closures from {}", count, path);
+    }
+
+    if let Some(ref command) = cfg.command {
+        return run_command(logger, &cfg, clock, settings.sources, command)
+    }
+
+    let paths = input::expand_paths(&cfg.input)?;
+    if paths.is_empty() {
+        return Err(err_msg("An input file is required"));
+    }
+    let filters = filter::BacktestFilters::new(settings.filters)?;
+    let json_columns = settings.json_columns.unwrap_or_else(datatables::default_columns);
+    datatables::check_columns(&json_columns).context("json_columns")?;
+
+    let mut strategies = cfg.strategies.iter()
+        .map(|s| s.parse::<cmlviz::Strategy>())
+        .collect::<Result<Vec<_>, _>>()?;
 
     let mut best_only = false;
 
     // the pre and post earnings options set a default value for best_only.
     if cfg.post_earnings {
-        cfg.strategies.extend(cmlviz::Strategy::postearnings_strategies().into_iter());
+        strategies.extend(cmlviz::Strategy::postearnings_strategies().into_iter());
         best_only = true;
     }
 
     if cfg.pre_earnings {
-        cfg.strategies.extend(cmlviz::Strategy::preearnings_strategies().into_iter());
+        strategies.extend(cmlviz::Strategy::preearnings_strategies().into_iter());
         best_only = false;
     }
 
@@ -113,24 +532,45 @@ fn run_it(logger : &slog::Logger) -> Result<(), Error> {
 
     // Doesn't really matter, but let's remove mutability.
     let best_only = best_only;
+    let day_count = cfg.day_count;
+
+    let backtests = input::read_backtests(logger, &paths, cfg.strategy_pattern.as_ref(), &json_columns)?;
+    if !backtests.rejects.is_empty() {
+        warn!(logger, "Skipped {} input rows that couldn't be read", backtests.rejects.len());
+        for reject in backtests.rejects.iter() {
+            warn!(logger, "  {}", reject);
+        }
 
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_path(&filename)
-        .context("opening csv")?;
+        if let Some(ref path) = cfg.rejects {
+            input::write_rejects(path, &backtests.rejects).context("writing rejected rows")?;
+            info!(logger, "Saved the rows that couldn't be read to {}", path);
+        }
+
+        if cfg.strict {
+            return Err(format_err!("{} input rows couldn't be read", backtests.rejects.len()));
+        }
+    }
 
-    // Read the file and group the tests by symbol.
-    info!(logger, "Reading file {}", filename);
-    let backtests_by_symbol = reader.deserialize::<cmlviz::BacktestResultInput>()
+    // Group the tests by symbol.
+    let mut filtered_out = Vec::new();
+    let backtests_by_symbol = backtests.tests
         .into_iter()
-        .map(|t| cmlviz::BacktestResult::from_input(t?))
-        .map(|t| t.expect("csv row"))
         .filter(|t| {
-            if cfg.strategies.len() > 0 && cfg.strategies.iter().find(|&&x| x == t.strategy).is_none() {
+            if strategies.len() > 0 && strategies.iter().find(|&&x| x == t.strategy).is_none() {
                 return false
             }
 
-            cfg.start_date.map_or(true, |x| t.next_earnings.date >= x) && cfg.end_date.map_or(true, |x| t.next_earnings.date <= x)
+            if !(cfg.start_date.map_or(true, |x| t.next_earnings.date >= x) && cfg.end_date.map_or(true, |x| t.next_earnings.date <= x)) {
+                return false
+            }
+
+            match filters.check(t) {
+                Some(reason) => {
+                    filtered_out.push(format!("{} {} {}: {}", t.symbol, t.strategy.short_name(), t.stats(), reason));
+                    false
+                },
+                None => true,
+            }
         })
         .fold(HashMap::<String, Vec<cmlviz::BacktestResult>>::new(), |mut acc, test| {
             acc
@@ -141,43 +581,58 @@ fn run_it(logger : &slog::Logger) -> Result<(), Error> {
         });
 
 
-    let mut headers = Headers::new();
-    headers.set(UserAgent::new("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_13_2) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/63.0.3239.132 Safari/537.36"));
+    if !filtered_out.is_empty() {
+        info!(logger, "Filtered out {} backtests", filtered_out.len());
+        for line in filtered_out.iter() {
+            info!(logger, "  {}", line);
+        }
+    }
 
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
-        .build()
-        .context("building client")?;
-
-    let mut earnings_cache : HashMap<String, earnings::EarningsGuess> = std::fs::File::open(EARNINGS_CACHE_NAME)
-        .map_err(Error::from)
-        .and_then(|f| serde_json::from_reader(f).map_err(Error::from))
-        .unwrap_or_else(|e| {
-            warn!(logger, "Couldn't load earnings cache: {}", e);
-            HashMap::new()
-        });
+    let mut sources = earnings::SourceRegistry::new(logger, settings.sources).context("setting up earnings sources")?;
+    let mut history = load_history()?;
+    if cfg.weight_by_accuracy {
+        for (source, accuracy) in history.accuracy() {
+            debug!(logger, "Scaling weight of {} by its accuracy {:.0}%", source, accuracy.rate() * 100.0);
+            sources.scale_weight(&source, accuracy.rate());
+        }
+    }
+
+    let fetch_mode = fetch_mode(&cfg);
+
+    // Replayed runs and runs as of another date should be reproducible, and cached guesses and history entries are for the
+    // real date, so those runs don't read or update the cache, or add to the history.
+    let use_cache = cfg.replay.is_none() && cfg.as_of.is_none();
+    let write_cache = use_cache && !cfg.no_cache;
+    // Recording needs every page fetched, so cached symbols are fetched again.
+    let read_cache = write_cache && !cfg.refresh && cfg.record.is_none();
+
+    let earnings_cache_path = cache_path(&cfg);
+    let cache_ttl = chrono::Duration::days(cfg.cache_ttl);
+    // A cache that can't be read would be overwritten when the run saves it, so it's an error rather than a warning.
+    let mut earnings_cache = if write_cache {
+        load_cache(logger, &earnings_cache_path)
+            .context("loading earnings cache, which can be skipped with --no-cache")?
+    } else {
+        cache::EarningsCache::default()
+    };
 
-    let uptodate_earnings_threshold = chrono::Local::today().naive_local() - chrono::Duration::days(2);
     let tests_with_earnings = backtests_by_symbol
         .into_iter()
         .filter_map(|(symbol, tests)| {
             info!(logger, "Processing symbol {}", symbol);
 
+            if let Some(date) = tests[0].prev_earnings_date() {
+                history.realize(&symbol, earnings::EarningsDateTime{ date: date, time: earnings::AnnounceTime::Unknown }, false);
+            }
+
             // Figure out our best guess at the earnings date based on the CML data and a bunch of other sources.
-            let mut guess = earnings_cache.get(&symbol)
-                .and_then(|guess| if guess.last_session < uptodate_earnings_threshold { None } else { Some(guess.clone()) } );
+            let mut guess = if read_cache { earnings_cache.get(&symbol, &clock, cache_ttl).cloned() } else { None };
 
             if guess.is_none() {
-                let mut earnings_dates = earnings::get_earnings_date_estimates(&logger, &client, symbol.as_str());
-                let test_date = earnings::SourcedEarningsTime{
-                    source: "CML".into(),
-                    datetime: tests[0].next_earnings,
-                };
-                earnings_dates.push(test_date);
-                guess = earnings::best_earnings_guess(&earnings_dates);
+                guess = fetch_guess(logger, &clock, &sources, &fetch_mode, &mut history, &symbol, Some(tests[0].next_earnings));
 
                 if guess.is_some() {
-                    earnings_cache.insert(symbol.clone(), guess.as_ref().unwrap().clone());
+                    earnings_cache.insert(&symbol, guess.as_ref().unwrap().clone(), chrono::Utc::now());
                 }
             }
 
@@ -187,28 +642,52 @@ fn run_it(logger : &slog::Logger) -> Result<(), Error> {
 
             let guess = guess.unwrap();
 
-            // The "best test" is just the one that has the highest average trade return.
-            // In general the win rates for the various strategies are close enough that it's not worth factoring it in
-            // beyond the effect that it already has on the average return.
+            let moved_from = if use_cache { record_guess(logger, &mut history, &symbol, clock.today(), &guess) } else { None };
+
+            if let Some(min_confidence) = cfg.min_confidence {
+                if guess.confidence * 100.0 < min_confidence {
+                    info!(logger, "Skipping {} with earnings date confidence {:.0}%", symbol, guess.confidence * 100.0);
+                    return None
+                }
+            }
+
+            // The "best test" is the one that ranks highest by `cfg.rank_by` (see `ranking::Ranking` for the choices).
             let active_tests;
             if best_only {
-                let best_test = cmlviz::get_best_test(&tests);
+                let best_test = cmlviz::get_best_test(&tests, cfg.rank_by);
                 active_tests = vec![best_test];
             } else {
-                let best_tests = cmlviz::get_best_test_per_strategy(&tests);
+                let best_tests = cmlviz::get_best_test_per_strategy(&tests, cfg.rank_by);
                 active_tests = best_tests.into_iter().map(|(_, v)| v).collect::<Vec<_>>();
             }
 
+            // A position that's already open can't move its open date, but it needs to be closed on the new date.
+            if let Some(ref previous) = moved_from {
+                let today = clock.today();
+                for &active_test in active_tests.iter() {
+                    let strategy = tests[active_test].strategy;
+                    let old_open = strategy.open_date(previous.last_session, day_count);
+                    let old_close = strategy.close_date(previous.last_session, day_count);
+                    let new_close = strategy.close_date(guess.last_session, day_count);
+                    if old_open <= today && old_close >= today && old_close != new_close {
+                        error!(logger, "Open position {} {} now closes on {} instead of {}",
+                            symbol, strategy.short_name(), new_close, old_close);
+                    }
+                }
+            }
+
             let output = active_tests.into_iter()
                 .map(|active_test| {
-                    let open_date = tests[active_test].strategy.open_date(guess.last_session);
-                    let close_date = tests[active_test].strategy.close_date(guess.last_session);
+                    let open_date = tests[active_test].strategy.open_date(guess.last_session, day_count);
+                    let close_date = tests[active_test].strategy.close_date(guess.last_session, day_count);
                     let key = (open_date, close_date, symbol.clone(), tests[active_test].strategy);
                     let result = TestsAndEarnings{
                         symbol: symbol.clone(),
                         tests: tests.clone(),
                         active_test_index: active_test,
                         earnings: guess.clone(),
+                        open_date: Some(open_date),
+                        close_date: Some(close_date),
                     };
 
                     (key, result)
@@ -220,61 +699,22 @@ fn run_it(logger : &slog::Logger) -> Result<(), Error> {
         .flat_map(|x| x)
         .collect::<BTreeMap<_, _>>();
 
-    std::fs::File::create(EARNINGS_CACHE_NAME)
-        .map_err(Error::from)
-        .and_then(|f| serde_json::to_writer(f, &earnings_cache).map_err(Error::from))
-        .context("writing earnings cache")?;
+    if write_cache {
+        earnings_cache.save(&earnings_cache_path).context("writing earnings cache")?;
+    }
+    if use_cache {
+        history.save(EARNINGS_HISTORY_NAME).context("writing earnings history")?;
+    }
 
-    // TODO Nice output formatting
-    let mut output = cfg.output
-        .map(|path| {
-            let b = Box::new(File::create(path)?);
-            let r : Result<Box<Write>, std::io::Error> = Ok(b);
-            r
-        })
-        .unwrap_or_else(|| Ok(Box::new(std::io::stdout())))
-        .context("Opening output file")?;
+    let mut output = open_output(&cfg.output)?;
 
     let mut raw_data_output = cfg.save_raw
         .map(|path| File::create(path))
         .map_or(Ok(None), |v| v.map(Some))?;
 
-    for ((open_date, close_date, symbol, strategy), data) in tests_with_earnings {
-
-        let active_test = &data.tests[data.active_test_index];
-        let mut best_others_sorted_by_return = cmlviz::get_best_test_per_strategy(&data.tests)
-            .into_iter()
-            .filter(|&(other_strategy, _)| other_strategy != strategy)
-            .map(|(strategy, index)| (strategy, &data.tests[index]))
-            .collect::<Vec<(cmlviz::Strategy, &cmlviz::BacktestResult)>>();
-        best_others_sorted_by_return.sort_by_key(|&(_, x)| -x.sort_key());
-        let other_strategies = best_others_sorted_by_return
-            .iter()
-            .map(|&(strategy, test)| format!("{}{}", strategy.abbreviation(), test.stats()) )
-            .join(", ");
-
-        let concurrences = data.earnings.concurrences.iter().map(|x| x.source.as_ref()).join(",");
-
-        let best_strategy_desc = format!("{} {}", active_test.strategy.short_name(), active_test.stats());
-
-        write!(output, "{open} - {close} : {symbol} {best_strategy} [{other_strategies}] [{prev_earnings}] [{sources}]",
-            open=open_date,
-            close=close_date,
-            symbol=symbol,
-            sources=concurrences,
-            best_strategy=best_strategy_desc,
-            other_strategies=other_strategies,
-            prev_earnings=active_test.prev_earnings_result)?;
-
-        if data.earnings.close_disagreements.len() > 0 || data.earnings.far_disagreements.len() > 0 {
-            let disagreements = data.earnings.close_disagreements.iter()
-                .chain(data.earnings.far_disagreements.iter())
-                .map(|x| format!("{}: {}", x.source, x.datetime))
-                .join(",");
-            write!(output, " [{}]", disagreements)?;
-        }
-
-        write!(output, "\n")?;
+    let mut trades = Vec::new();
+    for (_, data) in tests_with_earnings {
+        trades.push(data.trade(day_count, cfg.rank_by, sources.pages(&data.symbol, &data.earnings)));
 
         raw_data_output.as_mut().map_or(Ok(()), |mut w| {
             serde_json::to_writer(&mut w, &data)?;
@@ -284,16 +724,72 @@ fn run_it(logger : &slog::Logger) -> Result<(), Error> {
         })?;
     }
 
+    let plan = output::Plan{
+        as_of: clock.today(),
+        generated: chrono::Utc::now(),
+        trades: trades,
+    };
+    let options = output::Options{
+        event_time: cfg.event_time,
+        ranking: cfg.rank_by,
+    };
+    output::write(cfg.format, &options, &mut output, &plan)?;
+
     Ok(())
 }
 
 fn main() {
     let logger = init_logger();
 
-    if let Err(e) = run_it(&logger) {
-        let msg = e.causes()
-            .map(|e| e.to_string())
-            .join("\n  ");
-        error!(logger, "{}", msg);
+    let code = match run_it(&logger, Config::from_args()) {
+        Ok(()) => 0,
+        Err(e) => {
+            let msg = e.causes()
+                .map(|e| e.to_string())
+                .join("\n  ");
+            error!(logger, "{}", msg);
+            1
+        },
+    };
+
+    // The logger writes from another thread, so it has to be dropped to flush it before exiting.
+    drop(logger);
+    ::std::process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use testing::{logger, temp_path};
+
+    #[test]
+    fn today_uses_closures() {
+        let dir = temp_path("today");
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name : &str| dir.join(name).to_string_lossy().into_owned();
+
+        // Seven sessions before Monday, March 10, 2031 is February 27, or February 26 with March 3 closed. The plan
+        // doesn't have the dates, so they're worked out again.
+        let last_session = earnings::Date::from_ymd(2031, 3, 10);
+        let data = TestsAndEarnings{
+            symbol: "AAPL".to_string(),
+            tests: vec![cmlviz::BacktestResult::sample("call_7d_preearnings")],
+            active_test_index: 0,
+            earnings: earnings::EarningsGuess::sample(last_session),
+            open_date: None,
+            close_date: None,
+        };
+        fs::write(path("plan.json"), serde_json::to_string(&data).unwrap()).unwrap();
+        fs::write(path("closures.txt"), "2031-03-03\n").unwrap();
+
+        let (closures, output, plan) = (path("closures.txt"), path("today.txt"), path("plan.json"));
+        let args = vec!["earnings-trade-scheduler", "--as-of", "2031-02-26", "--closures", &closures, "-o", &output, "today", &plan];
+        let cfg = Config::from_clap(Config::clap().get_matches_from(args));
+        run_it(&logger(), cfg).unwrap();
+        let today = fs::read_to_string(&output).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(today.starts_with("Open on 2031-02-26:\n  AAPL E-7 Call"), "{}", today);
     }
 }
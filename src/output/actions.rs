@@ -0,0 +1,41 @@
+use std::io::Write;
+use failure::Error;
+use earnings::DatelikeExt;
+use super::{Plan, Trade};
+
+/// Only the positions to open or close on the plan's `as_of` date.
+pub fn write(output : &mut Write, plan : &Plan) -> Result<(), Error> {
+    let today = plan.as_of;
+    let opens = plan.trades.iter().filter(|t| t.open_date == today).collect::<Vec<_>>();
+    let mut closes = plan.trades.iter().filter(|t| t.close_date == today).collect::<Vec<_>>();
+    closes.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+    if let Some(time) = today.early_close() {
+        writeln!(output, "The market closes early at {} on {}.\n", time.format("%H:%M"), today)?;
+    }
+
+    writeln!(output, "Open on {}:", today)?;
+    write_section(output, &opens)?;
+    writeln!(output, "\nClose on {}:", today)?;
+    write_section(output, &closes)?;
+
+    Ok(())
+}
+
+fn write_section(output : &mut Write, trades : &[&Trade]) -> Result<(), Error> {
+    if trades.is_empty() {
+        writeln!(output, "  Nothing")?;
+    }
+
+    for trade in trades {
+        writeln!(output, "  {} {} {} (open {}, close {}, earnings {})",
+            trade.symbol,
+            trade.strategy_name,
+            trade.test.stats(),
+            trade.open_date,
+            trade.close_date,
+            trade.earnings.announcement())?;
+    }
+
+    Ok(())
+}
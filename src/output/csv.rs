@@ -0,0 +1,60 @@
+use std::io::Write;
+use csv;
+use failure::Error;
+use itertools::Itertools;
+use earnings::Date;
+use super::Plan;
+
+/// One row of the spreadsheet. The field names are the header.
+#[derive(Serialize)]
+struct Row<'a> {
+    open_date : Date,
+    close_date : Date,
+    symbol : &'a str,
+    strategy : &'static str,
+    strategy_name : &'static str,
+    win_rate : i32,
+    avg_trade_return : i32,
+    wins : usize,
+    losses : usize,
+    total_return : i32,
+    backtest_length : usize,
+    earnings_date : Date,
+    announce_time : String,
+    confidence : String,
+    concurring_sources : String,
+    disagreeing_sources : String,
+}
+
+pub fn write(output : &mut Write, plan : &Plan) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(output);
+    for trade in plan.trades.iter() {
+        let announcement = trade.earnings.announcement();
+        let disagreements = trade.earnings.close_disagreements.iter()
+            .chain(trade.earnings.far_disagreements.iter())
+            .map(|x| format!("{}: {}", x.source, x.datetime))
+            .join(", ");
+
+        writer.serialize(Row{
+            open_date: trade.open_date,
+            close_date: trade.close_date,
+            symbol: &trade.symbol,
+            strategy: trade.test.strategy.id(),
+            strategy_name: trade.strategy_name,
+            win_rate: trade.test.win_rate,
+            avg_trade_return: trade.test.avg_trade_return,
+            wins: trade.test.wins,
+            losses: trade.test.losses,
+            total_return: trade.test.total_return,
+            backtest_length: trade.test.backtest_length,
+            earnings_date: announcement.date,
+            announce_time: announcement.time.to_string(),
+            confidence: format!("{:.0}%", trade.earnings.confidence * 100.0),
+            concurring_sources: trade.earnings.concurrences.iter().map(|x| x.source.as_ref()).join(", "),
+            disagreeing_sources: disagreements,
+        })?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use std::io::Write;
+use chrono::{Datelike, Duration, NaiveTime};
+use failure::Error;
+use htmlescape::encode_minimal;
+use itertools::Itertools;
+use earnings::{Date, SourcedEarningsTime};
+use ranking::Ranking;
+use super::{Options, Plan, Trade};
+
+static STYLE : &'static str = "
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; width: 100%; margin-bottom: 2em; }
+th, td { border-bottom: 1px solid #ddd; padding: 0.4em 0.6em; text-align: left; vertical-align: top; }
+th { background: #f4f4f4; }
+.active { font-weight: bold; background: #e6f4ea; }
+.better { color: #b06000; font-weight: bold; }
+.pages a { font-size: 0.8em; margin-right: 0.5em; }
+.close-disagreement { background: #fff4cc; }
+.far-disagreement { background: #fbd9d9; }
+.early-close { color: #b06000; }
+";
+
+pub fn write(output : &mut Write, options : &Options, plan : &Plan) -> Result<(), Error> {
+    writeln!(output, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Earnings Trades as of {}</title>\n<style>{}</style>\n</head>\n<body>",
+        plan.as_of, STYLE)?;
+    writeln!(output, "<h1>Earnings Trades</h1>\n<p>As of {}</p>", plan.as_of)?;
+
+    // The trades are already in order of open date.
+    for (week, trades) in plan.trades.iter().group_by(|t| week_of(t.open_date)).into_iter() {
+        writeln!(output, "<h2>Week of {}</h2>\n<table>", week.format("%B %-d, %Y"))?;
+        writeln!(output, "<tr><th>Open</th><th>Close</th><th>Symbol</th><th>Strategy</th><th>Other Strategies</th><th>Previous Earnings</th><th>Earnings</th><th>Disagreements</th></tr>")?;
+        for trade in trades {
+            write_trade(output, options.ranking, trade)?;
+        }
+        writeln!(output, "</table>")?;
+    }
+
+    writeln!(output, "</body>\n</html>")?;
+    Ok(())
+}
+
+/// The Monday of the date's week.
+fn week_of(date : Date) -> Date {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+fn write_trade(output : &mut Write, ranking : Ranking, trade : &Trade) -> Result<(), Error> {
+    let date_cell = |date : Date, early_close : Option<NaiveTime>| {
+        match early_close {
+            Some(time) => format!("{} <span class=\"early-close\">(closes {})</span>", date, time.format("%H:%M")),
+            None => date.to_string(),
+        }
+    };
+
+    let pages = trade.source_pages.iter()
+        .map(|p| format!("<a href=\"{}\">{}</a>", encode_minimal(&p.url), encode_minimal(&p.source)))
+        .join("");
+
+    // Call out any other strategy that backtested better than the one being traded.
+    let others = trade.alternatives.iter()
+        .map(|test| {
+            let text = encode_minimal(&format!("{}{}", test.strategy.abbreviation(), test.stats()));
+            if ranking.score(test) > ranking.score(&trade.test) {
+                format!("<span class=\"better\">{}</span>", text)
+            } else {
+                text
+            }
+        })
+        .join(", ");
+
+    let earnings = format!("{} ({}: {:.0}%)",
+        trade.earnings.announcement(),
+        trade.earnings.concurrences.iter().map(|x| x.source.as_ref()).join(", "),
+        trade.earnings.confidence * 100.0);
+
+    let disagreement = |class : &str, d : &SourcedEarningsTime| {
+        format!("<span class=\"{}\">{}</span>", class, encode_minimal(&format!("{}: {}", d.source, d.datetime)))
+    };
+    let disagreements = trade.earnings.close_disagreements.iter()
+        .map(|d| disagreement("close-disagreement", d))
+        .chain(trade.earnings.far_disagreements.iter().map(|d| disagreement("far-disagreement", d)))
+        .join(" ");
+
+    writeln!(output, "<tr><td>{open}</td><td>{close}</td><td>{symbol}<div class=\"pages\">{pages}</div></td><td class=\"active\">{strategy}</td><td>{others}</td><td>{prev}</td><td>{earnings}</td><td>{disagreements}</td></tr>",
+        open=date_cell(trade.open_date, trade.open_early_close),
+        close=date_cell(trade.close_date, trade.close_early_close),
+        symbol=encode_minimal(&trade.symbol),
+        pages=pages,
+        strategy=encode_minimal(&format!("{} {}", trade.strategy_name, trade.test.stats())),
+        others=others,
+        prev=encode_minimal(&trade.test.prev_earnings_result),
+        earnings=encode_minimal(&earnings),
+        disagreements=disagreements)?;
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use std::io::Write;
+use chrono::{Datelike, Duration};
+use failure::Error;
+use itertools::Itertools;
+use earnings::Date;
+use super::{Options, Plan, Trade};
+
+/// How long a timed event lasts.
+const EVENT_MINUTES : i64 = 30;
+
+/// Lines longer than this many bytes are folded onto continuation lines.
+const MAX_LINE : usize = 75;
+
+/// Timed events are in market time.
+static TZID : &'static str = "America/New_York";
+
+/// US Eastern time, with the daylight saving rules in effect since 2007.
+static VTIMEZONE : &[&str] = &[
+    "BEGIN:VTIMEZONE",
+    "TZID:America/New_York",
+    "BEGIN:DAYLIGHT",
+    "TZOFFSETFROM:-0500",
+    "TZOFFSETTO:-0400",
+    "TZNAME:EDT",
+    "DTSTART:20070311T020000",
+    "RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=2SU",
+    "END:DAYLIGHT",
+    "BEGIN:STANDARD",
+    "TZOFFSETFROM:-0400",
+    "TZOFFSETTO:-0500",
+    "TZNAME:EST",
+    "DTSTART:20071104T020000",
+    "RRULE:FREQ=YEARLY;BYMONTH=11;BYDAY=1SU",
+    "END:STANDARD",
+    "END:VTIMEZONE",
+];
+
+pub fn write(output : &mut Write, options : &Options, plan : &Plan) -> Result<(), Error> {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//earnings-trade-scheduler//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+
+    if options.event_time.is_some() {
+        lines.extend(VTIMEZONE.iter().map(|line| line.to_string()));
+    }
+
+    let stamp = plan.generated.format("%Y%m%dT%H%M%SZ").to_string();
+    // Each new plan's events have to have a higher sequence than the last one's for calendars to take the changes, and
+    // the time it was made in seconds always goes up.
+    let sequence = plan.generated.timestamp();
+    for trade in plan.trades.iter() {
+        for &(label, date, early_close) in [
+            ("Open", trade.open_date, trade.open_early_close),
+            ("Close", trade.close_date, trade.close_early_close),
+        ].iter() {
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(format!("UID:{}", uid(trade, label)));
+            lines.push(format!("DTSTAMP:{}", stamp));
+            lines.push(format!("LAST-MODIFIED:{}", stamp));
+            lines.push(format!("SEQUENCE:{}", sequence));
+            match options.event_time {
+                Some(time) => {
+                    // On an early close day the event has to be over by the time the market closes.
+                    let length = Duration::minutes(EVENT_MINUTES);
+                    let time = match early_close {
+                        Some(close) if time + length > close => close - length,
+                        _ => time,
+                    };
+                    let start = date.and_time(time);
+                    lines.push(format!("DTSTART;TZID={}:{}", TZID, start.format("%Y%m%dT%H%M%S")));
+                    lines.push(format!("DTEND;TZID={}:{}", TZID, (start + length).format("%Y%m%dT%H%M%S")));
+                },
+                None => {
+                    lines.push(format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")));
+                    lines.push(format!("DTEND;VALUE=DATE:{}", date.succ().format("%Y%m%d")));
+                }
+            }
+            lines.push(format!("SUMMARY:{}", escape(&format!("{} {} {}", label, trade.symbol, trade.strategy_name))));
+            lines.push(format!("DESCRIPTION:{}", escape(&description(trade))));
+            lines.push("TRANSP:TRANSPARENT".to_string());
+            lines.push("END:VEVENT".to_string());
+        }
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    for line in lines {
+        output.write_all(fold(&line).as_bytes())?;
+        output.write_all(b"\r\n")?;
+    }
+
+    Ok(())
+}
+
+/// The same trade gets the same UID when the plan is made again, even if its dates have moved, so importing the new
+/// plan updates the events. Trades are told apart by the previous announcement in the backtest results, which doesn't
+/// change when the next one moves. Results without one fall back to the quarter of the last session before earnings.
+fn uid(trade : &Trade, label : &str) -> String {
+    let announcement = match trade.test.prev_earnings_date() {
+        Some(previous) => format!("after{}", previous.format("%Y%m%d")),
+        None => {
+            let earnings = trade.earnings.last_session;
+            format!("{}q{}", earnings.year(), quarter(earnings))
+        },
+    };
+
+    format!("{}-{}-{}-{}@earnings-trade-scheduler",
+        trade.symbol,
+        trade.test.strategy.id(),
+        announcement,
+        label.to_lowercase())
+}
+
+fn quarter(date : Date) -> u32 {
+    (date.month() - 1) / 3 + 1
+}
+
+fn description(trade : &Trade) -> String {
+    let mut lines = vec![
+        format!("{} {}", trade.strategy_name, trade.test.stats()),
+        format!("Earnings: {} ({}: {:.0}%)",
+            trade.earnings.announcement(),
+            trade.earnings.concurrences.iter().map(|x| x.source.as_ref()).join(", "),
+            trade.earnings.confidence * 100.0),
+    ];
+
+    let disagreements = trade.earnings.close_disagreements.iter()
+        .chain(trade.earnings.far_disagreements.iter())
+        .map(|x| format!("{}: {}", x.source, x.datetime))
+        .join(", ");
+    if !disagreements.is_empty() {
+        lines.push(format!("Disagreements: {}", disagreements));
+    }
+
+    if !trade.alternatives.is_empty() {
+        let others = trade.alternatives.iter()
+            .map(|test| format!("{}{}", test.strategy.abbreviation(), test.stats()))
+            .join(", ");
+        lines.push(format!("Other strategies: {}", others));
+    }
+
+    for &(label, time) in [("Open", trade.open_early_close), ("Close", trade.close_early_close)].iter() {
+        if let Some(time) = time {
+            lines.push(format!("{} day closes early at {}", label, time.format("%H:%M")));
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Escape a TEXT value.
+fn escape(text : &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Split a content line into lines of at most `MAX_LINE` bytes, without splitting a character.
+fn fold(line : &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE {
+            folded.push_str("\r\n ");
+            // The leading space counts toward the continuation line's length.
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
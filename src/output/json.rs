@@ -0,0 +1,10 @@
+use std::io::Write;
+use failure::Error;
+use serde_json;
+use super::Plan;
+
+pub fn write(output : &mut Write, plan : &Plan) -> Result<(), Error> {
+    serde_json::to_writer_pretty(&mut *output, plan)?;
+    output.write_all(b"\n")?;
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use chrono::NaiveTime;
+use failure::Error;
+use earnings::Date;
+use super::{Plan, Trade};
+
+/// The positions to open and close on one day.
+#[derive(Default)]
+struct Day<'a> {
+    opens : Vec<&'a Trade>,
+    closes : Vec<&'a Trade>,
+}
+
+pub fn write(output : &mut Write, plan : &Plan) -> Result<(), Error> {
+    let mut days : BTreeMap<Date, Day> = BTreeMap::new();
+    for trade in plan.trades.iter() {
+        days.entry(trade.open_date).or_insert_with(Day::default).opens.push(trade);
+        days.entry(trade.close_date).or_insert_with(Day::default).closes.push(trade);
+    }
+
+    // The trades are in order of open date, which doesn't mean much for the closes.
+    for day in days.values_mut() {
+        day.closes.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    }
+
+    writeln!(output, "# Earnings Trades as of {}", plan.as_of)?;
+
+    for (date, day) in days.iter() {
+        writeln!(output, "\n## {}", date.format("%A, %B %-d, %Y"))?;
+        write_section(output, "Open", &day.opens, |t| t.open_early_close)?;
+        write_section(output, "Close", &day.closes, |t| t.close_early_close)?;
+    }
+
+    Ok(())
+}
+
+fn write_section<F>(output : &mut Write, label : &str, trades : &[&Trade], early_close : F) -> Result<(), Error>
+    where F : Fn(&Trade) -> Option<NaiveTime> {
+
+    if trades.is_empty() {
+        return Ok(())
+    }
+
+    writeln!(output, "\n**{}**\n", label)?;
+    for trade in trades {
+        write!(output, "- **{}** {} {} (earnings {}, {:.0}% confidence)",
+            trade.symbol,
+            trade.strategy_name,
+            trade.test.stats(),
+            trade.earnings.announcement(),
+            trade.earnings.confidence * 100.0)?;
+
+        if let Some(time) = early_close(trade) {
+            write!(output, ", market closes at {}", time.format("%H:%M"))?;
+        }
+
+        writeln!(output)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,282 @@
+use std::cmp::Reverse;
+use std::io::Write;
+use std::str::FromStr;
+use failure::{Error, err_msg};
+use chrono::{DateTime, NaiveTime, Utc};
+use cmlviz::{self, BacktestResult};
+use earnings::{Date, DatelikeExt, EarningsGuess, SourcePage};
+use ranking::Ranking;
+
+mod actions;
+mod csv;
+mod html;
+mod ics;
+mod json;
+mod markdown;
+mod text;
+
+/// How the trade plan is written.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Format {
+    /// One line per trade.
+    Text,
+    /// A `Plan` document.
+    Json,
+    /// A header row, then one row per trade.
+    Csv,
+    /// An iCalendar file with an event for each open and close.
+    Ics,
+    /// A report with a table of trades for each week.
+    Html,
+    /// An agenda of the positions to open and close each day.
+    Markdown,
+    /// Only the positions to open and close on the plan's date.
+    Actions,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "ics" => Ok(Format::Ics),
+            "html" => Ok(Format::Html),
+            "markdown" => Ok(Format::Markdown),
+            "actions" => Ok(Format::Actions),
+            _ => Err(err_msg(format!("Unknown format {}, expected text, json, csv, ics, html, markdown or actions", s))),
+        }
+    }
+}
+
+/// One planned trade.
+#[derive(Debug,Clone,Serialize)]
+pub struct Trade {
+    pub open_date : Date,
+    pub close_date : Date,
+    /// Set when the position opens on a shortened session.
+    pub open_early_close : Option<NaiveTime>,
+    /// Set when the position closes on a shortened session.
+    pub close_early_close : Option<NaiveTime>,
+    pub symbol : String,
+    pub strategy_name : &'static str,
+    /// The backtest for the strategy being traded.
+    pub test : BacktestResult,
+    /// The best backtest for each of the symbol's other strategies, best first by the ranking the trade was made with.
+    pub alternatives : Vec<BacktestResult>,
+    pub earnings : EarningsGuess,
+    /// The pages the earnings date was scraped from.
+    pub source_pages : Vec<SourcePage>,
+}
+
+impl Trade {
+    pub fn new(open_date : Date, close_date : Date, tests : &[BacktestResult], active_test_index : usize, earnings : &EarningsGuess,
+        source_pages : Vec<SourcePage>, ranking : Ranking) -> Trade {
+        let test = &tests[active_test_index];
+        let mut alternatives = cmlviz::get_best_test_per_strategy(tests, ranking)
+            .into_iter()
+            .filter(|&(other_strategy, _)| other_strategy != test.strategy)
+            .map(|(_, index)| tests[index].clone())
+            .collect::<Vec<_>>();
+        alternatives.sort_by_key(|x| Reverse(ranking.score(x)));
+
+        Trade{
+            open_date: open_date,
+            close_date: close_date,
+            open_early_close: open_date.early_close(),
+            close_early_close: close_date.early_close(),
+            symbol: test.symbol.clone(),
+            strategy_name: test.strategy.short_name(),
+            test: test.clone(),
+            alternatives: alternatives,
+            earnings: earnings.clone(),
+            source_pages: source_pages,
+        }
+    }
+}
+
+/// The trades from one run, in order of open date.
+#[derive(Debug,Clone,Serialize)]
+pub struct Plan {
+    /// The date the plan was made for.
+    pub as_of : Date,
+    /// When the plan was actually made, even with `--as-of`, so each new calendar export supersedes the last.
+    pub generated : DateTime<Utc>,
+    pub trades : Vec<Trade>,
+}
+
+/// Settings for the formats that use them.
+#[derive(Debug,Clone,Default)]
+pub struct Options {
+    /// The time of day for calendar events. They're all-day events if this isn't set.
+    pub event_time : Option<NaiveTime>,
+    /// How backtests are compared when highlighting better alternatives.
+    pub ranking : Ranking,
+}
+
+pub fn write(format : Format, options : &Options, output : &mut Write, plan : &Plan) -> Result<(), Error> {
+    match format {
+        Format::Text => text::write(output, plan),
+        Format::Json => json::write(output, plan),
+        Format::Csv => csv::write(output, plan),
+        Format::Ics => ics::write(output, options, plan),
+        Format::Html => html::write(output, options, plan),
+        Format::Markdown => markdown::write(output, plan),
+        Format::Actions => actions::write(output, plan),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use earnings::{AnnounceTime, SourcedEarningsTime};
+    use serde_json;
+
+    fn test(strategy : &str, avg_trade_return : i32) -> BacktestResult {
+        BacktestResult::sample(strategy).with_returns(avg_trade_return, 300)
+    }
+
+    fn plan() -> Plan {
+        let tests = vec![
+            test("call_7d_preearnings", 25),
+            test("iron_condor_post_earnings", 15),
+            test("strangle_7d_preearnings", 20),
+        ];
+        let earnings = EarningsGuess::sample(Date::from_ymd(2018, 10, 29))
+            .with_concurrences(vec![SourcedEarningsTime::sample("FinViz", 2018, 10, 29, AnnounceTime::AfterMarket)])
+            .with_far_disagreements(vec![SourcedEarningsTime::sample("CML", 2018, 11, 20, AnnounceTime::Unknown)])
+            .with_confidence(0.5);
+
+        Plan{
+            as_of: Date::from_ymd(2018, 10, 1),
+            generated: DateTime::from_utc(Date::from_ymd(2018, 10, 1).and_hms(12, 0, 0), Utc),
+            trades: vec![Trade::new(Date::from_ymd(2018, 10, 18), Date::from_ymd(2018, 10, 29), &tests, 0, &earnings, vec![
+                SourcePage{ source: "FinViz".to_string(), url: "https://finviz.com/quote.ashx?t=AAPL".to_string() },
+            ], Ranking::Weighted)],
+        }
+    }
+
+    fn render(format : Format) -> String {
+        render_with(format, &Options::default())
+    }
+
+    fn render_with(format : Format, options : &Options) -> String {
+        let mut buf = Vec::new();
+        write(format, options, &mut buf, &plan()).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn text_line() {
+        assert_eq!(render(Format::Text),
+            "2018-10-18 - 2018-10-29 : AAPL E-7 Call (25%,10/2) [E-7S(20%,10/2), E+1IC(15%,10/2)] [2018-07-31] [FinViz: 50%] [CML: 2018-11-20]\n");
+    }
+
+    #[test]
+    fn csv_rows() {
+        assert_eq!(render(Format::Csv),
+            "open_date,close_date,symbol,strategy,strategy_name,win_rate,avg_trade_return,wins,losses,total_return,backtest_length,earnings_date,announce_time,confidence,concurring_sources,disagreeing_sources\n\
+             2018-10-18,2018-10-29,AAPL,call_7d_preearnings,E-7 Call,83,25,10,2,300,3,2018-10-29,AMC,50%,FinViz,CML: 2018-11-20\n");
+    }
+
+    #[test]
+    fn json_document() {
+        let value : serde_json::Value = serde_json::from_str(&render(Format::Json)).unwrap();
+        let trade = &value["trades"][0];
+        assert_eq!(value["as_of"], "2018-10-01");
+        assert_eq!(trade["open_date"], "2018-10-18");
+        assert_eq!(trade["strategy_name"], "E-7 Call");
+        assert_eq!(trade["test"]["strategy"], "call_7d_preearnings");
+        assert_eq!(trade["alternatives"][0]["strategy"], "strangle_7d_preearnings");
+        assert_eq!(trade["earnings"]["last_session"], "2018-10-29");
+    }
+
+    #[test]
+    fn ics_all_day_events() {
+        let ics = render(Format::Ics);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics.contains("\r\nUID:AAPL-call_7d_preearnings-after20180731-open@earnings-trade-scheduler\r\n"));
+        assert!(ics.contains("\r\nUID:AAPL-call_7d_preearnings-after20180731-close@earnings-trade-scheduler\r\n"));
+        assert!(ics.contains("\r\nDTSTAMP:20181001T120000Z\r\nLAST-MODIFIED:20181001T120000Z\r\nSEQUENCE:1538395200\r\n"));
+        assert!(!ics.contains("VTIMEZONE"));
+        assert!(ics.contains("\r\nDTSTART;VALUE=DATE:20181018\r\nDTEND;VALUE=DATE:20181019\r\n"));
+        assert!(ics.contains("\r\nSUMMARY:Close AAPL E-7 Call\r\n"));
+        assert!(ics.lines().all(|line| line.len() <= 75));
+    }
+
+    #[test]
+    fn ics_uid_survives_quarter_change() {
+        let uid = |ics : &str| ics.lines().find(|line| line.starts_with("UID:")).unwrap().to_string();
+        let before = render(Format::Ics);
+
+        let mut plan = plan();
+        plan.trades[0].earnings.last_session = Date::from_ymd(2019, 1, 2);
+        let mut buf = Vec::new();
+        write(Format::Ics, &Options::default(), &mut buf, &plan).unwrap();
+        assert_eq!(uid(&String::from_utf8(buf).unwrap()), uid(&before));
+    }
+
+    #[test]
+    fn ics_timed_events() {
+        let options = Options{ event_time: Some(NaiveTime::from_hms(9, 45, 0)), ..Options::default() };
+        let ics = render_with(Format::Ics, &options);
+        assert!(ics.contains("\r\nBEGIN:VTIMEZONE\r\nTZID:America/New_York\r\n"));
+        assert!(ics.contains("\r\nDTSTART;TZID=America/New_York:20181029T094500\r\nDTEND;TZID=America/New_York:20181029T101500\r\n"));
+    }
+
+    #[test]
+    fn ics_timed_events_end_by_early_close() {
+        let mut plan = plan();
+        plan.trades[0].close_early_close = Some(NaiveTime::from_hms(13, 0, 0));
+        let options = Options{ event_time: Some(NaiveTime::from_hms(15, 45, 0)), ..Options::default() };
+        let mut buf = Vec::new();
+        write(Format::Ics, &options, &mut buf, &plan).unwrap();
+        let ics = String::from_utf8(buf).unwrap();
+        assert!(ics.contains("\r\nDTSTART;TZID=America/New_York:20181018T154500\r\nDTEND;TZID=America/New_York:20181018T161500\r\n"));
+        assert!(ics.contains("\r\nDTSTART;TZID=America/New_York:20181029T123000\r\nDTEND;TZID=America/New_York:20181029T130000\r\n"));
+    }
+
+    #[test]
+    fn html_report() {
+        let html = render(Format::Html);
+        assert!(html.contains("<h2>Week of October 15, 2018</h2>"));
+        assert!(html.contains("<td class=\"active\">E-7 Call (25%,10/2)</td>"));
+        assert!(html.contains("<a href=\"https://finviz.com/quote.ashx?t=AAPL\">FinViz</a>"));
+        assert!(html.contains("<span class=\"far-disagreement\">CML: 2018-11-20</span>"));
+        assert!(!html.contains("class=\"better\""));
+    }
+
+    #[test]
+    fn markdown_agenda() {
+        assert_eq!(render(Format::Markdown), "# Earnings Trades as of 2018-10-01
+
+## Thursday, October 18, 2018
+
+**Open**
+
+- **AAPL** E-7 Call (25%,10/2) (earnings 2018-10-29 AMC, 50% confidence)
+
+## Monday, October 29, 2018
+
+**Close**
+
+- **AAPL** E-7 Call (25%,10/2) (earnings 2018-10-29 AMC, 50% confidence)
+");
+    }
+
+    #[test]
+    fn actions_for_the_day() {
+        let mut plan = plan();
+        plan.as_of = Date::from_ymd(2018, 10, 29);
+        let mut buf = Vec::new();
+        write(Format::Actions, &Options::default(), &mut buf, &plan).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "Open on 2018-10-29:
+  Nothing
+
+Close on 2018-10-29:
+  AAPL E-7 Call (25%,10/2) (open 2018-10-18, close 2018-10-29, earnings 2018-10-29 AMC)
+");
+    }
+}
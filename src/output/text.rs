@@ -0,0 +1,46 @@
+use std::io::Write;
+use failure::Error;
+use itertools::Itertools;
+use super::Plan;
+
+pub fn write(output : &mut Write, plan : &Plan) -> Result<(), Error> {
+    for trade in plan.trades.iter() {
+        let other_strategies = trade.alternatives
+            .iter()
+            .map(|test| format!("{}{}", test.strategy.abbreviation(), test.stats()) )
+            .join(", ");
+
+        let concurrences = trade.earnings.concurrences.iter().map(|x| x.source.as_ref()).join(",");
+
+        let best_strategy_desc = format!("{} {}", trade.strategy_name, trade.test.stats());
+
+        write!(output, "{open} - {close} : {symbol} {best_strategy} [{other_strategies}] [{prev_earnings}] [{sources}: {confidence:.0}%]",
+            open=trade.open_date,
+            close=trade.close_date,
+            symbol=trade.symbol,
+            sources=concurrences,
+            confidence=trade.earnings.confidence * 100.0,
+            best_strategy=best_strategy_desc,
+            other_strategies=other_strategies,
+            prev_earnings=trade.test.prev_earnings_result)?;
+
+        if trade.earnings.close_disagreements.len() > 0 || trade.earnings.far_disagreements.len() > 0 {
+            let disagreements = trade.earnings.close_disagreements.iter()
+                .chain(trade.earnings.far_disagreements.iter())
+                .map(|x| format!("{}: {}", x.source, x.datetime))
+                .join(",");
+            write!(output, " [{}]", disagreements)?;
+        }
+
+        let early_closes = [("open", trade.open_early_close), ("close", trade.close_early_close)].iter()
+            .filter_map(|&(label, time)| time.map(|time| format!("{} {}", label, time.format("%H:%M"))))
+            .join(", ");
+        if !early_closes.is_empty() {
+            write!(output, " [early close: {}]", early_closes)?;
+        }
+
+        write!(output, "\n")?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+use failure::{Error, err_msg};
+use cmlviz::BacktestResult;
+
+/// The z-score for a 95% confidence interval.
+const WILSON_Z : f64 = 1.96;
+
+/// How many break-even trades `Ranking::ShrunkReturn` adds to each backtest.
+const PRIOR_TRADES : f64 = 5.0;
+
+/// How backtests are compared when picking the best one for a symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Ranking {
+    /// Average return times the number of trades. This heavily favors long backtests.
+    #[default]
+    Weighted,
+    /// Average return per trade.
+    AvgReturn,
+    /// The lower bound of the 95% Wilson score interval on the win rate, so a short winning streak doesn't rank above a
+    /// long record.
+    Wilson,
+    /// The mean of a normal prior at break-even, updated with the backtest's trades, which pulls the average return of
+    /// short backtests toward zero.
+    ShrunkReturn,
+    /// Total return per year of backtest.
+    AnnualReturn,
+}
+
+impl FromStr for Ranking {
+    type Err = Error;
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s {
+            "weighted" => Ok(Ranking::Weighted),
+            "avg-return" => Ok(Ranking::AvgReturn),
+            "wilson" => Ok(Ranking::Wilson),
+            "shrunk-return" => Ok(Ranking::ShrunkReturn),
+            "annual-return" => Ok(Ranking::AnnualReturn),
+            _ => Err(err_msg(format!("Unknown ranking {}, expected weighted, avg-return, wilson, shrunk-return or annual-return", s))),
+        }
+    }
+}
+
+impl Ranking {
+    pub fn score(&self, test : &BacktestResult) -> Score {
+        let trades = (test.wins + test.losses) as f64;
+        let avg_return = f64::from(test.avg_trade_return);
+        let score = match *self {
+            Ranking::Weighted => avg_return * trades,
+            Ranking::AvgReturn => avg_return,
+            Ranking::Wilson => {
+                if trades == 0.0 {
+                    0.0
+                } else {
+                    let p = test.wins as f64 / trades;
+                    let z2 = WILSON_Z * WILSON_Z;
+                    let center = p + z2 / (2.0 * trades);
+                    let spread = WILSON_Z * (p * (1.0 - p) / trades + z2 / (4.0 * trades * trades)).sqrt();
+                    (center - spread) / (1.0 + z2 / trades)
+                }
+            },
+            Ranking::ShrunkReturn => avg_return * trades / (trades + PRIOR_TRADES),
+            Ranking::AnnualReturn => {
+                if test.backtest_length == 0 { 0.0 } else { f64::from(test.total_return) / test.backtest_length as f64 }
+            },
+        };
+
+        Score(score)
+    }
+}
+
+/// A backtest's score under a ranking. Higher is better.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score(pub f64);
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other : &Score) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other : &Score) -> Ordering {
+        self.0.partial_cmp(&other.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test(wins : usize, losses : usize, avg_trade_return : i32, total_return : i32, backtest_length : usize) -> BacktestResult {
+        BacktestResult::sample("call_7d_preearnings")
+            .with_trades(wins, losses)
+            .with_returns(avg_trade_return, total_return)
+            .with_length(backtest_length)
+    }
+
+    #[test]
+    fn wilson_prefers_long_records() {
+        let lucky = test(2, 0, 40, 80, 1);
+        let steady = test(20, 4, 15, 300, 6);
+        assert!(Ranking::Wilson.score(&steady) > Ranking::Wilson.score(&lucky));
+        assert!(Ranking::AvgReturn.score(&lucky) > Ranking::AvgReturn.score(&steady));
+
+        let score = Ranking::Wilson.score(&test(8, 2, 10, 100, 3)).0;
+        assert!((score - 0.4902).abs() < 0.0001, "{}", score);
+    }
+
+    #[test]
+    fn shrunk_and_annual_returns() {
+        assert_eq!(Ranking::ShrunkReturn.score(&test(4, 1, 20, 100, 2)), Score(10.0));
+        assert_eq!(Ranking::AnnualReturn.score(&test(4, 1, 20, 100, 2)), Score(50.0));
+        assert_eq!(Ranking::AnnualReturn.score(&test(4, 1, 20, 100, 0)), Score(0.0));
+    }
+}
@@ -0,0 +1,36 @@
+//! Helpers shared by the unit tests.
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, Mutex};
+use slog::{Discard, Drain, Logger, Never, OwnedKVList, Record};
+
+/// A logger that drops everything.
+pub fn logger() -> Logger {
+    Logger::root(Discard, o!())
+}
+
+/// A logger that keeps the messages it's given, so a test can check what was logged.
+pub fn recording_logger() -> (Logger, Arc<Mutex<Vec<String>>>) {
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    (Logger::root(Recorder(messages.clone()), o!()), messages)
+}
+
+struct Recorder(Arc<Mutex<Vec<String>>>);
+
+impl Drain for Recorder {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record : &Record, _ : &OwnedKVList) -> Result<(), Never> {
+        self.0.lock().unwrap().push(record.msg().to_string());
+        Ok(())
+    }
+}
+
+/// Where a test can put `name` in the temp directory. The process ID keeps concurrent runs apart, and since tests run in
+/// parallel, each one needs a name of its own.
+pub fn temp_path(name : &str) -> PathBuf {
+    env::temp_dir().join(format!("earnings-trade-scheduler-{}-{}", process::id(), name))
+}